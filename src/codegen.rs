@@ -2,42 +2,214 @@
 extern crate inkwell_llvm12 as inkwell;
 
 use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::{self};
+use std::path::PathBuf;
+use std::str::FromStr;
 
 use crate::core::{get_core_path, install_core, is_core_installed};
-use crate::parser::{parse, AstNode, AST};
-use crate::{todo_feature, unrecoverable_error};
-use inkwell::attributes::AttributeLoc;
+use crate::debug_info::DebugInfo;
+use crate::diagnostics::Diagnostic;
+use crate::error::FireworkError;
+use crate::modules::ModuleScope;
+use crate::parser::{parse, AstNode, Location, SourceModule, Span};
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::execution_engine::ExecutionEngine;
+use inkwell::memory_buffer::MemoryBuffer;
 use inkwell::module::{Linkage, Module};
-use inkwell::support::LLVMString;
+use inkwell::passes::{PassManager, PassManagerBuilder};
 use inkwell::targets::{
-    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+};
+use inkwell::types::{
+    AnyType, AnyTypeEnum, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, IntType,
+    StructType,
+};
+use inkwell::values::{
+    AnyValueEnum, BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallSiteValue, FunctionValue,
+    IntValue, PointerValue, StructValue,
 };
-use inkwell::types::{BasicMetadataTypeEnum, StringRadix};
-use inkwell::values::{AnyValueEnum, BasicMetadataValueEnum, BasicValueEnum, FunctionValue};
 use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
 
 macro_rules! any_value_enum_to_basic_value_enum {
     ( $x:expr ) => {
         match $x {
             AnyValueEnum::IntValue(x) => BasicValueEnum::IntValue(x),
+            AnyValueEnum::FloatValue(x) => BasicValueEnum::FloatValue(x),
             AnyValueEnum::PointerValue(x) => BasicValueEnum::PointerValue(x),
-            a => todo_feature!(format!("Type `{:?}` not implemented or doesn't exist", a)),
+            AnyValueEnum::StructValue(x) => BasicValueEnum::StructValue(x),
+            a => return Err(not_implemented(format!("Type `{:?}`", a))),
         }
     };
 
     () => {};
 }
 
+/// Value of an expression that was evaluated at compile time
+#[derive(Debug, Clone, PartialEq)]
+enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+/// Builtin sum types, compiled to named structs whose first field tells whether the value was
+/// built by ```Some```/```Ok``` or by ```None```/```Err```, followed by each constructor's payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SumType {
+    Option,
+    Result,
+}
+
+/// Constructors of the builtin sum types
+const CONSTRUCTORS: [&str; 4] = ["Some", "None", "Ok", "Err"];
+
+/// LLVM's ```tailcc``` calling convention, calls in tail position between functions using it
+/// are guaranteed to be tail call optimized
+const TAIL_CALL_CONV: u32 = 18;
+
+/// LLVM's ```fastcc``` calling convention, used by targets that don't support ```tailcc```,
+/// only calls whose caller and callee have compatible frames are tail call optimized
+const FAST_CALL_CONV: u32 = 8;
+
+/// LLVM IR of the runtime WebAssembly modules use instead of libc
+const WASI_RUNTIME: &str = include_str!("runtime/wasi.ll");
+
+/// Runtime routine called when a program panics, prints a message with the source location
+/// of the panic to stderr and exits the program
+const PANIC_FUNCTION: &str = "firework_panic";
+
+/// Exit code of programs that panicked
+const PANIC_EXIT_CODE: u64 = 101;
+
+/// Operators that are compiled at the call site with runtime checks when overflow checks are
+/// enabled
+const CHECKED_OPERATORS: [&str; 7] = ["+", "-", "*", "/", "%", "<<", ">>"];
+
+/// Comparisons of integers, compiled at the call site with their signed and unsigned predicates
+const COMPARISONS: [(&str, IntPredicate, IntPredicate); 6] = [
+    ("==", IntPredicate::EQ, IntPredicate::EQ),
+    ("!=", IntPredicate::NE, IntPredicate::NE),
+    ("<", IntPredicate::SLT, IntPredicate::ULT),
+    (">", IntPredicate::SGT, IntPredicate::UGT),
+    ("<=", IntPredicate::SLE, IntPredicate::ULE),
+    (">=", IntPredicate::SGE, IntPredicate::UGE),
+];
+
+/// Functions that are compiled at the call site, because they need the call's source location
+/// or work with values of any type
+const BUILTINS: [&str; 6] = ["panic", "assert", "Some", "None", "Ok", "Err"];
+
+/// Functions of ```Option``` and ```Result``` compiled at the call site, unlike the
+/// ```BUILTINS``` they can be shadowed by functions of the program
+const SUM_HELPERS: [&str; 3] = ["map", "and_then", "unwrap_or"];
+
+/// How much the compiled code is optimized, set with ```-O```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Optimizes like ```O2``` but favors smaller code
+    Os,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!(
+                "Unknown optimization level `{}`, expected one of 0, 1, 2, 3, s",
+                level
+            )),
+        }
+    }
+}
+
+impl From<OptLevel> for OptimizationLevel {
+    fn from(level: OptLevel) -> Self {
+        match level {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+/// Stream the IR is printed to by ```--print-after-passes```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IrStream {
+    Stdout,
+    Stderr,
+}
+
+/// Machine the code is generated for, every option defaults to the host's
+#[derive(Debug, Clone, Default)]
+pub struct TargetOptions {
+    /// Target triple, e.g. ```aarch64-unknown-linux-gnu```
+    pub triple: Option<String>,
+    /// CPU the code is tuned for, e.g. ```cortex-a72```
+    pub cpu: Option<String>,
+    /// CPU features that are enabled or disabled, e.g. ```+avx2,-sse4.1```
+    pub features: Option<String>,
+}
+
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
     execution_engine: ExecutionEngine<'ctx>,
     core: Module<'ctx>,
+    /// Function arguments and ```let``` bindings visible from the code being compiled
+    variables: RefCell<HashMap<String, BasicValueEnum<'ctx>>>,
+    /// Functions visible from the module being compiled
+    scope: RefCell<ModuleScope>,
+    /// Maps every function to the functions its body may call
+    call_graph: RefCell<HashMap<String, HashSet<String>>>,
+    /// Values of the constants that were evaluated at compile time
+    constants: RefCell<HashMap<String, BasicValueEnum<'ctx>>>,
+    /// Constants that are evaluated the first time they're used
+    lazy_constants: RefCell<HashSet<String>>,
+    /// Path of the source file being compiled, reported by runtime panics
+    source_path: RefCell<String>,
+    /// Whether arithmetic traps on overflow and division by zero instead of wrapping
+    overflow_checks: bool,
+    /// Type the expression being compiled should have, if it's known
+    expected_type: RefCell<Option<BasicTypeEnum<'ctx>>>,
+    /// Values of unsigned integer types, LLVM's integers don't have a signedness
+    unsigned_values: RefCell<HashSet<IntValue<'ctx>>>,
+    /// Functions returning an unsigned integer type
+    unsigned_returns: RefCell<HashSet<String>>,
+    /// Functions declared with ```export```, the only ones visible outside of libraries
+    exported: RefCell<HashSet<String>>,
+    /// Machine the compiled code is written for
+    target: TargetOptions,
+    opt_level: OptLevel,
+    /// Where the IR is printed once the optimization passes ran, if it is
+    print_after_passes: Option<IrStream>,
+    /// Whether ```compile``` emits DWARF debug information
+    emit_debug_info: bool,
+    /// Debug information of the compiled program, created by ```compile```
+    debug_info: RefCell<Option<DebugInfo<'ctx>>>,
+    /// Warnings about the compiled code
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    /// Source of the core library, the installed core is used if it isn't set
+    core_source: Option<String>,
 }
 
 impl<'ctx> CodeGen<'ctx> {
@@ -60,10 +232,118 @@ impl<'ctx> CodeGen<'ctx> {
             builder,
             execution_engine,
             core,
+            variables: RefCell::new(HashMap::new()),
+            scope: RefCell::new(ModuleScope::default()),
+            call_graph: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashMap::new()),
+            lazy_constants: RefCell::new(HashSet::new()),
+            source_path: RefCell::new(String::new()),
+            overflow_checks: true,
+            expected_type: RefCell::new(None),
+            unsigned_values: RefCell::new(HashSet::new()),
+            unsigned_returns: RefCell::new(HashSet::new()),
+            exported: RefCell::new(HashSet::new()),
+            target: TargetOptions::default(),
+            opt_level: OptLevel::default(),
+            print_after_passes: None,
+            emit_debug_info: false,
+            debug_info: RefCell::new(None),
+            diagnostics: RefCell::new(vec![]),
+            core_source: None,
+        }
+    }
+
+    /// Enables or disables the runtime checks of arithmetic operators, they're enabled by default
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
+    /// Sets how much the code is optimized by ```optimize``` and the code generator, the
+    /// execution engine is created with its own level
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    /// Prints the IR to ```stream``` once the optimization passes ran
+    pub fn set_print_after_passes(&mut self, stream: Option<IrStream>) {
+        self.print_after_passes = stream;
+    }
+
+    /// Compiles programs with ```source``` as the core library instead of the installed one
+    pub fn set_core_source(&mut self, source: String) {
+        self.core_source = Some(source);
+    }
+
+    /// Emits debug information, so debuggers can step through the compiled program
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.emit_debug_info = enabled;
+    }
+
+    /// Sets the machine code is generated for, the module takes its triple and data layout
+    pub fn set_target(&mut self, target: TargetOptions) -> Result<(), FireworkError> {
+        self.target = target;
+
+        let machine = self.target_machine(RelocMode::Default)?;
+        let data_layout = machine.get_target_data().get_data_layout();
+
+        for module in [&self.module, &self.core] {
+            module.set_triple(&machine.get_triple());
+            module.set_data_layout(&data_layout);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the machine code is generated for
+    pub fn target(&self) -> &TargetOptions {
+        &self.target
+    }
+
+    /// Returns the architecture of the target triple, or an empty string for the host
+    fn target_arch(&self) -> &str {
+        self.target
+            .triple
+            .as_deref()
+            .and_then(|triple| triple.split('-').next())
+            .unwrap_or_default()
+    }
+
+    /// Whether the code is compiled to a WebAssembly module
+    pub fn is_wasm_target(&self) -> bool {
+        self.target_arch().starts_with("wasm")
+    }
+
+    /// Returns the calling convention of functions that aren't called from C, tail calls are
+    /// only guaranteed if it's ```tailcc```
+    fn internal_call_conv(&self) -> u32 {
+        let arch = self.target_arch();
+
+        // LLVM's RISC-V and WebAssembly backends don't implement tailcc
+        if arch.starts_with("riscv") || arch.starts_with("wasm") {
+            FAST_CALL_CONV
+        } else {
+            TAIL_CALL_CONV
         }
     }
 
-    fn available_name(&self, name: u8) -> String {
+    /// Returns the function the builder is currently inserting instructions into
+    fn current_function(&self) -> FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap()
+    }
+
+    /// Type of expressions that are only evaluated for their side effects
+    fn unit_type(&self) -> StructType<'ctx> {
+        self.context.struct_type(&[], false)
+    }
+
+    fn unit_value(&self) -> StructValue<'ctx> {
+        self.unit_type().const_named_struct(&[])
+    }
+
+    fn available_name(&self, name: usize) -> String {
         let name_as_string = name.to_string();
 
         if self.module.get_global(&name_as_string).is_some() {
@@ -73,142 +353,223 @@ impl<'ctx> CodeGen<'ctx> {
         }
     }
 
-    fn compile_astnode(&self, node: AstNode) -> Result<AnyValueEnum<'ctx>, &'static str> {
-        // TODO: anonymous functions, module imports and declarations, enums and unescaping strings
+    fn compile_astnode(&self, node: AstNode) -> Result<AnyValueEnum<'ctx>, FireworkError> {
+        self.set_debug_location(&node.span().location());
+
+        // TODO: anonymous functions, enums and unescaping strings
         match node {
-            AstNode::Int(int) => Ok(self
-                .context
-                .i64_type()
-                .const_int_from_string(&int.to_string(), StringRadix::Decimal)
-                .unwrap()
-                .into()),
-            AstNode::Str(str) => Ok(unsafe {
-                self.builder
-                    .build_global_string(&(str + "\0"), &self.available_name(0))
-                    .as_pointer_value()
-                    .into()
-            }),
-            AstNode::Boolean(bool) => Ok(self
+            AstNode::Int(int, span) => {
+                // Integers take the type expected from them, e.g. the type of a parameter
+                let int_type = match *self.expected_type.borrow() {
+                    Some(BasicTypeEnum::IntType(int_type)) if int_type.get_bit_width() > 1 => {
+                        int_type
+                    }
+                    _ => self.context.i64_type(),
+                };
+                let width = int_type.get_bit_width();
+
+                if !fits_in(int, width) {
+                    return Err(type_error(
+                        format!("The integer `{}` doesn't fit in {} bits", int, width),
+                        span,
+                    ));
+                }
+
+                Ok(int_type.const_int(int as u64, true).into())
+            }
+            AstNode::Float(float, _) => Ok(self.context.f64_type().const_float(float).into()),
+            AstNode::Str(str, _) => Ok(self.build_string(&str).into()),
+            AstNode::Boolean(bool, _) => Ok(self
                 .context
                 .bool_type()
                 .const_int(bool as u64, false)
                 .into()),
-            AstNode::Char(char) => Ok(unsafe {
-                self.builder
-                    .build_global_string(&(char.to_string() + "\0"), &self.available_name(0))
-                    .as_pointer_value()
-                    .into()
-            }),
-            AstNode::Fn {
-                name,
-                return_type,
-                args,
-                value,
-            } => Ok(self
-                .compile_function(AstNode::Fn {
-                    name,
-                    return_type,
-                    args,
-                    value,
-                })
-                .into()),
+            AstNode::Char(char, _) => Ok(self.build_string(&char.to_string()).into()),
+            AstNode::Unit(_) => Ok(self.unit_value().into()),
+            // Imports are resolved by the project before anything gets compiled, and external
+            // functions are declared along with the file's functions
+            AstNode::ModuleImport { .. }
+            | AstNode::ModuleDeclaration { .. }
+            | AstNode::ExternFn { .. } => Ok(self.unit_value().into()),
+            // Constants were already evaluated while declaring the file's functions
+            AstNode::Fn { name, .. }
+                if self
+                    .constants
+                    .borrow()
+                    .contains_key(&self.scope.borrow().definition_symbol(&name)) =>
+            {
+                Ok(self.unit_value().into())
+            }
+            node @ AstNode::Fn { .. } => Ok(self.compile_function(node)?.into()),
             AstNode::IfElse {
                 condition,
                 stmt_false,
                 stmt_true,
+                ..
             } => {
-                let bool_true = self.context.bool_type().const_int(1, false);
+                let cond = self.compile_condition(*condition)?;
 
-                let value = self.compile_astnode(*stmt_true).unwrap();
+                let current_function = self.current_function();
 
-                let cond = self.compile_astnode(*condition).unwrap();
+                let then_bb = self.context.append_basic_block(current_function, "then");
+                let else_bb = self.context.append_basic_block(current_function, "else");
+                let cont_bb = self.context.append_basic_block(current_function, "ifcont");
 
-                let cond = self.builder.build_int_compare(
-                    IntPredicate::EQ,
-                    cond.into_int_value(),
-                    bool_true,
-                    "ifcond",
-                );
+                self.builder
+                    .build_conditional_branch(cond, then_bb, else_bb);
 
-                let else_branch = self.compile_astnode(*stmt_false).unwrap();
+                // Branches that panic don't reach the end of the if statement
+                let mut incoming = vec![];
+
+                for (block, stmt) in [(then_bb, stmt_true), (else_bb, stmt_false)] {
+                    self.builder.position_at_end(block);
+                    let value = self.with_expected_type(self.branch_type(&incoming), || {
+                        self.compile_astnode(*stmt)
+                    })?;
+
+                    if self.is_unreachable() {
+                        self.builder.build_unreachable();
+                    } else {
+                        self.builder.build_unconditional_branch(cont_bb);
+                        incoming.push((
+                            any_value_enum_to_basic_value_enum!(value),
+                            self.builder.get_insert_block().unwrap(),
+                        ));
+                    }
+                }
 
-                let last_function = self.module.get_last_function().unwrap();
+                self.builder.position_at_end(cont_bb);
 
-                let then_bb = self.context.append_basic_block(last_function, "then");
-                let else_bb = self.context.append_basic_block(last_function, "else");
-                let cont_bb = self.context.append_basic_block(last_function, "ifcont");
+                if incoming.is_empty() {
+                    return Ok(self.unit_value().into());
+                }
 
-                self.builder
-                    .build_conditional_branch(cond, then_bb, else_bb);
+                let phi = self.builder.build_phi(incoming[0].0.get_type(), "iftmp");
 
-                self.builder.position_at_end(then_bb);
-                self.builder.build_unconditional_branch(cont_bb);
+                phi.add_incoming(
+                    &incoming
+                        .iter()
+                        .map(|(value, block)| (value as &dyn BasicValue, *block))
+                        .collect::<Vec<_>>(),
+                );
 
-                let then_bb = self.builder.get_insert_block().unwrap();
+                Ok(phi.as_basic_value().into())
+            }
+            AstNode::Do(stmts, _) => {
+                let outer_scope = self.variables.borrow().clone();
+                let mut value = self.unit_value().into();
 
-                self.builder.position_at_end(else_bb);
-                self.builder.build_unconditional_branch(cont_bb);
+                for stmt in stmts {
+                    value = self.compile_astnode(stmt)?;
+                }
 
-                let else_bb = self.builder.get_insert_block().unwrap();
+                *self.variables.borrow_mut() = outer_scope;
 
-                self.builder.position_at_end(cont_bb);
+                Ok(value)
+            }
+            AstNode::LetBinding {
+                name,
+                value_type,
+                value,
+                span,
+            } => {
+                let expected_type = value_type
+                    .as_ref()
+                    .map(|value_type| self.compile_basic_type(*value_type.clone()))
+                    .transpose()?;
+                let value = any_value_enum_to_basic_value_enum!(
+                    self.with_expected_type(expected_type, || self.compile_astnode(*value))?
+                );
 
-                let phi = self.builder.build_phi(self.context.i64_type(), "iftmp");
+                if let (Some(expected_type), Some(value_type)) = (expected_type, value_type) {
+                    if expected_type != value.get_type() {
+                        let actual_type = self.type_name(value.get_type());
+
+                        return Err(Diagnostic::error(format!(
+                            "Mismatched types, `{}` is not of type `{}`",
+                            name,
+                            self.type_name(expected_type)
+                        ))
+                        .with_span(span)
+                        .with_suggestion(
+                            format!("change the type of `{}` to `{}`", name, actual_type),
+                            value_type.span(),
+                            actual_type,
+                        )
+                        .into());
+                    }
+                }
 
-                phi.add_incoming(&[
-                    (&any_value_enum_to_basic_value_enum!(value), then_bb),
-                    (&any_value_enum_to_basic_value_enum!(else_branch), else_bb),
-                ]);
+                self.variables.borrow_mut().insert(name, value);
 
-                Ok(phi.as_basic_value().into())
+                Ok(self.unit_value().into())
             }
-            AstNode::Identifier { name, args } => {
-                let arg_values = args
-                    .iter()
-                    .cloned()
-                    .map(|node| {
-                        let a = self.compile_astnode(node).unwrap();
-                        match a {
-                            AnyValueEnum::IntValue(x) => BasicMetadataValueEnum::IntValue(x),
-                            AnyValueEnum::ArrayValue(x) => BasicMetadataValueEnum::ArrayValue(x),
-                            AnyValueEnum::PointerValue(x) => {
-                                BasicMetadataValueEnum::PointerValue(x)
-                            }
-                            a => {
-                                todo_feature!(format!("Compiling {:?}", a))
-                            }
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                let last_function = self.module.get_last_function().unwrap();
-                let attr = last_function.get_string_attribute(AttributeLoc::Return, &name);
-
-                Ok(if let Some(arg) = attr {
-                    last_function
-                        .get_nth_param(
-                            arg.get_string_value()
-                                .to_str()
-                                .unwrap()
-                                .parse::<u32>()
-                                .unwrap(),
-                        )
-                        .unwrap()
-                        .into()
-                } else {
-                    let called = self.builder.build_call(
-                        self.get_function(&name).unwrap_or_else(|| {
-                            unrecoverable_error!(format!("Function {} not found!", name))
-                        }),
-                        arg_values.as_slice(),
-                        &name,
+            AstNode::Identifier {
+                name, args, span, ..
+            } => {
+                if args.is_empty() {
+                    if let Some(value) = self.variables.borrow().get(&name) {
+                        return Ok((*value).into());
+                    }
+
+                    let symbol = self.resolve(&name, span)?;
+
+                    if let Some(value) = self.constants.borrow().get(&symbol) {
+                        return Ok((*value).into());
+                    }
+                }
+
+                let symbol = self.resolve(&name, span)?;
+
+                if symbol == "&&" || symbol == "||" {
+                    return Ok(self.compile_short_circuit(&symbol, args, span)?.into());
+                }
+
+                if BUILTINS.contains(&symbol.as_str()) {
+                    return self.compile_builtin(&symbol, args, span);
+                }
+
+                if self.is_sum_helper(&symbol) {
+                    return Ok(self.compile_sum_helper(&symbol, args, span)?.into());
+                }
+
+                if self.is_checked_operator(&symbol) {
+                    return Ok(self.compile_checked_arithmetic(&symbol, args, span)?.into());
+                }
+
+                if let Some(predicates) = comparison_predicates(&symbol) {
+                    return Ok(self
+                        .compile_comparison(&symbol, predicates, args, span)?
+                        .into());
+                }
+
+                let called = self.compile_call(&name, args, span)?;
+
+                if self.is_recursive_call(called) {
+                    self.warn(
+                        Diagnostic::warning(format!(
+                            "Recursive call to `{}` in `{}` is not in tail position and may overflow the stack",
+                            name,
+                            self.current_function().get_name().to_string_lossy()
+                        ))
+                        .with_span(span)
+                        .with_code("non-tail-recursion")
+                        .with_label("not in tail position")
+                        .with_help("make the call the last expression of the function"),
                     );
-                    called.set_tail_call(true);
-                    called.try_as_basic_value().left().unwrap().into()
+                }
+
+                Ok(match called.try_as_basic_value().left() {
+                    Some(value) => value.into(),
+                    None => self.unit_value().into(),
                 })
             }
-            AstNode::InParens(node) => {
-                let compiled_astnode = self.compile_astnode(*node).unwrap();
+            AstNode::Match { value, arms, span } => {
+                Ok(self.compile_match(*value, arms, false, span)?.unwrap())
+            }
+            AstNode::Try(node, span) => Ok(self.compile_try(*node, span)?.into()),
+            AstNode::InParens(node, _) => {
+                let compiled_astnode = self.compile_astnode(*node)?;
 
                 let alloca = self.builder.build_alloca(
                     any_value_enum_to_basic_value_enum!(compiled_astnode).get_type(),
@@ -225,297 +586,3074 @@ impl<'ctx> CodeGen<'ctx> {
                     .build_load(alloca, alloca.get_name().to_string_lossy().borrow())
                     .into())
             }
-            a => {
-                todo_feature!(format!("Compiling {:?}", a))
-            }
+            a => Err(not_implemented(format!("Compiling {:?}", a))),
         }
     }
 
-    fn compile_function(&self, node: AstNode) -> FunctionValue<'ctx> {
-        match node {
-            AstNode::Fn {
-                name,
-                return_type,
-                args,
-                value,
-            } => {
-                let args_vec: Vec<(AstNode, AstNode)> = match *args {
-                    AstNode::FnArgs(x) => x,
-                    _ => unreachable!(),
-                };
-
-                let fn_type = match self.compile_type(*return_type) {
-                    BasicMetadataTypeEnum::IntType(x) => x.fn_type(
-                        &args_vec
-                            .iter()
-                            .cloned()
-                            .map(|(_, types)| self.compile_type(types))
-                            .collect::<Vec<_>>()[..],
-                        false,
-                    ),
-                    BasicMetadataTypeEnum::PointerType(x) => x.fn_type(
-                        &args_vec
-                            .iter()
-                            .cloned()
-                            .map(|(_, types)| self.compile_type(types))
-                            .collect::<Vec<_>>()[..],
-                        false,
-                    ),
-                    _ => panic!(),
-                };
-                let function = self.module.add_function(&name, fn_type, None);
-                let basic_block = self.context.append_basic_block(function, "entry");
-
-                self.builder.position_at_end(basic_block);
+    /// Checks whether an identifier calls a function rather than referring to a variable or a
+    /// constant
+    fn is_call(&self, name: &str, args: &[AstNode]) -> bool {
+        // Names that can't be resolved are compiled as calls, which reports the error
+        let symbol = match self.scope.borrow().resolve(name) {
+            Ok(symbol) => symbol,
+            Err(_) => return true,
+        };
+
+        if symbol == "&&"
+            || symbol == "||"
+            || BUILTINS.contains(&symbol.as_str())
+            || self.is_sum_helper(&symbol)
+            || self.is_checked_operator(&symbol)
+            || comparison_predicates(&symbol).is_some()
+        {
+            return false;
+        }
 
-                let params = function.get_params();
+        if !args.is_empty() || self.variables.borrow().contains_key(name) {
+            return !args.is_empty();
+        }
 
-                for arg in &params {
-                    let index = params.iter().position(|curr_arg| arg == curr_arg).unwrap();
-                    let name = match &args_vec[index].0 {
-                        AstNode::Identifier { name, args: _ } => name,
-                        _ => unreachable!(),
-                    };
-                    let attr = self
-                        .context
-                        .create_string_attribute(name, &index.to_string());
-                    function.add_attribute(AttributeLoc::Return, attr)
-                }
+        !self.constants.borrow().contains_key(&symbol)
+    }
 
-                let basic_value =
-                    any_value_enum_to_basic_value_enum!(self.compile_astnode(*value).unwrap());
+    /// Adds a null terminated string to the module and returns a pointer to its first character
+    fn build_string(&self, string: &str) -> PointerValue<'ctx> {
+        let i8_type = self.context.i8_type();
+        let bytes = string
+            .bytes()
+            .chain(std::iter::once(0))
+            .map(|byte| i8_type.const_int(byte as u64, false))
+            .collect::<Vec<_>>();
+        let value = i8_type.const_array(&bytes);
+
+        let global = self
+            .module
+            .add_global(value.get_type(), None, &self.available_name(0));
+        global.set_initializer(&value);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_addr(true);
+
+        global
+            .as_pointer_value()
+            .const_cast(i8_type.ptr_type(AddressSpace::Generic))
+    }
 
-                self.builder.build_return(Some(&basic_value));
+    /// Compiles a boolean expression used as the condition of an if statement
+    fn compile_condition(&self, node: AstNode) -> Result<IntValue<'ctx>, FireworkError> {
+        let bool_true = self.context.bool_type().const_int(1, false);
+        let span = node.span();
 
-                function
-            }
-            _ => panic!("Not a function!"),
+        match self.with_expected_type(None, || self.compile_astnode(node))? {
+            AnyValueEnum::IntValue(cond) if cond.get_type().get_bit_width() == 1 => Ok(self
+                .builder
+                .build_int_compare(IntPredicate::EQ, cond, bool_true, "ifcond")),
+            _ => Err(type_error("Conditions have to be of type `bool`", span)),
         }
     }
 
-    fn add_default_functions(&self) {
-        let i64_type = self.context.i64_type();
-        let bool_type = self.context.bool_type();
+    /// Builds a call to a function, using the calling convention of the called function
+    fn compile_call(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<CallSiteValue<'ctx>, FireworkError> {
+        let symbol = self.resolve(name, span)?;
+        let function = self
+            .get_function(&symbol)
+            .ok_or_else(|| type_error(format!("Function `{}` not found", name), span))?;
+        let function_type = function.get_type();
+        let param_types = function_type.get_param_types();
+
+        if !function_type.is_var_arg() && args.len() != param_types.len() {
+            return Err(type_error(
+                format!(
+                    "`{}` takes {} argument{} but {} {} given",
+                    name,
+                    param_types.len(),
+                    if param_types.len() == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "was" } else { "were" }
+                ),
+                span,
+            ));
+        }
 
-        // Puts
-        let func_type = i64_type.fn_type(
-            &[self
-                .context
-                .i8_type()
-                .ptr_type(AddressSpace::Generic)
-                .into()],
-            false,
-        );
+        let arg_values = args
+            .into_iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let arg_span = node.span();
+                let param_type = param_types.get(index).copied();
+                let a = self.with_expected_type(param_type, || self.compile_astnode(node))?;
+                let value = match (a, param_type) {
+                    (AnyValueEnum::IntValue(x), Some(BasicTypeEnum::IntType(param_type))) => {
+                        self.coerce_int(x, param_type).into()
+                    }
+                    (AnyValueEnum::ArrayValue(x), _) => x.into(),
+                    (a, _) => any_value_enum_to_basic_value_enum!(a),
+                };
 
-        self.module
-            .add_function("puts", func_type, Some(Linkage::External));
+                // Variadic arguments can be of any type
+                match param_type {
+                    Some(param_type) if param_type != value.get_type() => Err(type_error(
+                        format!(
+                            "Mismatched types, argument {} of `{}` is of type `{}` instead of `{}`",
+                            index + 1,
+                            name,
+                            self.type_name(value.get_type()),
+                            self.type_name(param_type)
+                        ),
+                        arg_span,
+                    )),
+                    _ => Ok(BasicMetadataValueEnum::from(value)),
+                }
+            })
+            .collect::<Result<Vec<_>, FireworkError>>()?;
 
-        // Printf
-        let func_type = i64_type.fn_type(
-            &[self
-                .context
-                .i8_type()
-                .ptr_type(AddressSpace::Generic)
-                .into()],
-            true,
-        );
+        // The arguments moved the location to themselves
+        self.set_debug_location(&span.location());
 
-        self.module
-            .add_function("printf", func_type, Some(Linkage::External));
+        // Calls to functions that return `Unit` can't be named
+        let call_name = if function.get_type().get_return_type().is_some() {
+            name
+        } else {
+            ""
+        };
 
-        // bool_eq
-        let func_type = self
-            .context
-            .bool_type()
-            .fn_type(&[bool_type.into(), bool_type.into()], false);
-        let func = self.module.add_function("bool_eq", func_type, None);
+        let called = self
+            .builder
+            .build_call(function, arg_values.as_slice(), call_name);
+        called.set_call_convention(function.get_call_conventions());
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+        if self.unsigned_returns.borrow().contains(&symbol) {
+            if let Some(BasicValueEnum::IntValue(value)) = called.try_as_basic_value().left() {
+                self.unsigned_values.borrow_mut().insert(value);
+            }
+        }
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+        Ok(called)
+    }
 
-        self.builder
-            .build_return(Some(&self.builder.build_int_compare(
-                inkwell::IntPredicate::EQ,
-                lhs,
-                rhs,
-                "eq",
-            )));
-
-        // ==
-        let func_type = self
-            .context
-            .bool_type()
-            .fn_type(&[i64_type.into(), i64_type.into()], false);
-        let func = self.core.add_function("==", func_type, None);
+    /// Converts an integer to an integer type of another size, sign extending it if needed,
+    /// or zero extending it if it has an unsigned type
+    ///
+    /// Booleans are never converted, so they can't be passed as integers by accident
+    fn coerce_int(&self, value: IntValue<'ctx>, int_type: IntType<'ctx>) -> IntValue<'ctx> {
+        let (width, target_width) = (value.get_type().get_bit_width(), int_type.get_bit_width());
+
+        if width == 1 || target_width == 1 || width == target_width {
+            value
+        } else if width < target_width && self.unsigned_values.borrow().contains(&value) {
+            self.builder.build_int_z_extend(value, int_type, "zext")
+        } else if width < target_width {
+            self.builder.build_int_s_extend(value, int_type, "sext")
+        } else {
+            self.builder.build_int_truncate(value, int_type, "trunc")
+        }
+    }
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+    /// Compiles an expression whose value is returned from the current function
+    ///
+    /// Calls in tail position are marked ```tail```. When both the caller and the callee use
+    /// ```tailcc```, LLVM guarantees that they are optimized to jumps regardless of the
+    /// optimization level, so recursion doesn't grow the stack. LLVM's C API can't mark calls
+    /// ```musttail```, so on targets falling back to ```fastcc``` the marker is only a hint and
+    /// recursive calls are reported like calls that aren't in tail position.
+    fn compile_tail(&self, node: AstNode) -> Result<(), FireworkError> {
+        self.set_debug_location(&node.span().location());
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+        match node {
+            AstNode::IfElse {
+                condition,
+                stmt_false,
+                stmt_true,
+                ..
+            } => {
+                let cond = self.compile_condition(*condition)?;
 
-        self.builder
-            .build_return(Some(&self.builder.build_int_compare(
-                inkwell::IntPredicate::EQ,
-                lhs,
-                rhs,
-                "eq",
-            )));
+                let current_function = self.current_function();
 
-        // +
+                let then_bb = self.context.append_basic_block(current_function, "then");
+                let else_bb = self.context.append_basic_block(current_function, "else");
 
-        let func_type = self
-            .context
-            .i64_type()
-            .fn_type(&[i64_type.into(), i64_type.into()], false);
+                self.builder
+                    .build_conditional_branch(cond, then_bb, else_bb);
 
-        let func = self.core.add_function("+", func_type, None);
+                self.builder.position_at_end(then_bb);
+                self.compile_tail(*stmt_true)?;
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+                self.builder.position_at_end(else_bb);
+                self.compile_tail(*stmt_false)?;
+            }
+            AstNode::Do(mut stmts, _) if !stmts.is_empty() => {
+                let outer_scope = self.variables.borrow().clone();
+                let last = stmts.pop().unwrap();
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+                for stmt in stmts {
+                    self.compile_astnode(stmt)?;
+                }
 
-        self.builder
-            .build_return(Some(&self.builder.build_int_add(lhs, rhs, "sum")));
+                self.compile_tail(last)?;
 
-        // -
+                *self.variables.borrow_mut() = outer_scope;
+            }
+            AstNode::InParens(node, _) => self.compile_tail(*node)?,
+            AstNode::Match { value, arms, span } => {
+                self.compile_match(*value, arms, true, span)?;
+            }
+            AstNode::Identifier {
+                name, args, span, ..
+            } if self.is_call(&name, &args) => {
+                let current_function = self.current_function();
+                let called = self.compile_call(&name, args, span)?;
+                let callee = called.get_called_fn_value();
+
+                let is_tail_call = current_function.get_call_conventions()
+                    == self.internal_call_conv()
+                    && callee.get_call_conventions() == self.internal_call_conv()
+                    && current_function.get_type().get_return_type()
+                        == callee.get_type().get_return_type();
+
+                // Tail calls between functions using fastcc are only optimized when possible
+                let is_guaranteed = is_tail_call && self.internal_call_conv() == TAIL_CALL_CONV;
+
+                if !is_guaranteed && self.is_recursive_call(called) {
+                    let note = if is_tail_call {
+                        format!(
+                            "tail calls are only guaranteed with the tailcc calling convention, which the {} target doesn't support",
+                            self.target_arch()
+                        )
+                    } else {
+                        "tail calls need the caller and the callee to use the same calling convention and return type".to_string()
+                    };
 
-        let func_type = self
-            .context
-            .i64_type()
-            .fn_type(&[i64_type.into(), i64_type.into()], false);
+                    self.warn(
+                        Diagnostic::warning(format!(
+                            "Recursive call to `{}` in `{}` may not be compiled as a tail call and may overflow the stack",
+                            name,
+                            current_function.get_name().to_string_lossy()
+                        ))
+                        .with_code("non-tail-recursion")
+                        .with_span(span)
+                        .with_note(note),
+                    );
+                }
 
-        let func = self.core.add_function("-", func_type, None);
+                if is_tail_call {
+                    called.set_tail_call(true);
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+                    match called.try_as_basic_value().left() {
+                        Some(value) => self.builder.build_return(Some(&value)),
+                        None => self.builder.build_return(None),
+                    };
+                } else {
+                    let value = match called.try_as_basic_value().left() {
+                        Some(value) => value.into(),
+                        None => self.unit_value().into(),
+                    };
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+                    self.build_function_return(value, span)?;
+                }
+            }
+            node => {
+                let span = node.span();
+                let value = self.compile_astnode(node)?;
+                self.build_function_return(value, span)?;
+            }
+        }
 
-        self.builder
-            .build_return(Some(&self.builder.build_int_sub(lhs, rhs, "sub")));
+        Ok(())
+    }
 
-        // *
+    /// Returns a value from the current function, integers are converted to its return type
+    fn build_function_return(
+        &self,
+        value: AnyValueEnum<'ctx>,
+        span: Span,
+    ) -> Result<(), FireworkError> {
+        let current_function = self.current_function();
+
+        if self.is_unreachable() {
+            self.builder.build_unreachable();
+        } else if current_function.get_name().to_bytes() == b"main" {
+            // The exit code can be narrower than the i32 returned to C, e.g. a call returning u8
+            let exit_code = match value {
+                AnyValueEnum::IntValue(value) => self.coerce_int(value, self.context.i32_type()),
+                _ => self.context.i32_type().const_zero(),
+            };
+
+            self.builder.build_return(Some(&exit_code));
+        } else if let Some(return_type) = current_function.get_type().get_return_type() {
+            let value = match (value, return_type) {
+                (AnyValueEnum::IntValue(value), BasicTypeEnum::IntType(int_type)) => {
+                    self.coerce_int(value, int_type).into()
+                }
+                (value, _) => any_value_enum_to_basic_value_enum!(value),
+            };
+
+            if value.get_type() != return_type {
+                return Err(type_error(
+                    format!(
+                        "Mismatched types, `{}` returns `{}` but its value is of type `{}`",
+                        current_function.get_name().to_string_lossy(),
+                        self.type_name(return_type),
+                        self.type_name(value.get_type())
+                    ),
+                    span,
+                ));
+            }
 
-        let func_type = self
-            .context
-            .i64_type()
-            .fn_type(&[i64_type.into(), i64_type.into()], false);
+            self.builder.build_return(Some(&value));
+        } else {
+            self.builder.build_return(None);
+        }
 
-        let func = self.core.add_function("*", func_type, None);
+        Ok(())
+    }
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+    /// Checks whether a call can lead back to the function it's made from
+    fn is_recursive_call(&self, called: CallSiteValue<'ctx>) -> bool {
+        let caller = self
+            .current_function()
+            .get_name()
+            .to_string_lossy()
+            .to_string();
+        let callee = called
+            .get_called_fn_value()
+            .get_name()
+            .to_string_lossy()
+            .to_string();
+
+        let call_graph = self.call_graph.borrow();
+        let mut visited = HashSet::new();
+        let mut stack = vec![callee];
+
+        while let Some(function) = stack.pop() {
+            if function == caller {
+                return true;
+            }
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+            if visited.insert(function.clone()) {
+                if let Some(called) = call_graph.get(&function) {
+                    stack.extend(called.iter().cloned());
+                }
+            }
+        }
 
-        self.builder
-            .build_return(Some(&self.builder.build_int_mul(lhs, rhs, "mul")));
+        false
+    }
+
+    /// Collects the symbols of the functions an expression may call
+    fn called_functions(&self, node: &AstNode, locals: &[String], called: &mut HashSet<String>) {
+        match node {
+            AstNode::Identifier { name, args, .. } => {
+                if !args.is_empty() || !locals.contains(name) {
+                    if let Ok(symbol) = self.scope.borrow().resolve(name) {
+                        called.insert(symbol);
+                    }
+                }
+
+                args.iter()
+                    .for_each(|arg| self.called_functions(arg, locals, called));
+            }
+            AstNode::IfElse {
+                condition,
+                stmt_true,
+                stmt_false,
+                ..
+            } => {
+                self.called_functions(condition, locals, called);
+                self.called_functions(stmt_true, locals, called);
+                self.called_functions(stmt_false, locals, called);
+            }
+            AstNode::Do(stmts, _) => stmts
+                .iter()
+                .for_each(|stmt| self.called_functions(stmt, locals, called)),
+            AstNode::LetBinding { value, .. }
+            | AstNode::InParens(value, _)
+            | AstNode::Try(value, _) => self.called_functions(value, locals, called),
+            AstNode::Match { value, arms, .. } => {
+                self.called_functions(value, locals, called);
+                arms.iter()
+                    .for_each(|(_, expr)| self.called_functions(expr, locals, called));
+            }
+            _ => (),
+        }
+    }
+
+    /// Adds a function's prototype to the module without compiling its body
+    fn declare_function(&self, node: &AstNode) -> Result<FunctionValue<'ctx>, FireworkError> {
+        match node {
+            AstNode::Fn {
+                name,
+                return_type,
+                args,
+                value: _,
+                exported,
+                span,
+            } => {
+                let args_vec = match &**args {
+                    AstNode::FnArgs(x, _) => x,
+                    _ => unreachable!(),
+                };
+
+                let param_types = args_vec
+                    .iter()
+                    .cloned()
+                    .map(|(_, types)| self.compile_type(types))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let name = self.scope.borrow().definition_symbol(name);
+                let returns_unit = is_unit_type(return_type);
+
+                if self.get_function(&name).is_some() {
+                    return Err(type_error(
+                        format!("Function `{}` is defined more than once", name),
+                        *span,
+                    ));
+                }
+
+                if BUILTINS.contains(&name.as_str()) {
+                    return Err(type_error(
+                        format!("`{}` is a builtin and can't be redefined", name),
+                        *span,
+                    ));
+                }
+
+                let fn_type = if name == "main" {
+                    if !args_vec.is_empty() {
+                        return Err(type_error("`main` can't take any arguments", args.span()));
+                    }
+
+                    if !returns_unit
+                        && !matches!(&**return_type, AstNode::Type(name, _) if name == "i64")
+                    {
+                        return Err(type_error(
+                            "`main` has to return either `Unit` or `i64`",
+                            return_type.span(),
+                        ));
+                    }
+
+                    // main returns a C int so that it can be used as the program's entry point
+                    self.context.i32_type().fn_type(&[], false)
+                } else {
+                    self.function_type(return_type, &param_types, false)?
+                };
+
+                if *exported {
+                    let unsupported = args_vec
+                        .iter()
+                        .map(|(_, param_type)| param_type)
+                        .find(|param_type| {
+                            is_unit_type(param_type) || self.c_type(param_type).is_none()
+                        })
+                        .or_else(|| Some(&**return_type).filter(|t| self.c_type(t).is_none()));
+
+                    if let Some(unsupported) = unsupported {
+                        return Err(type_error(
+                            format!(
+                                "`{}` can't be exported, `{}` has no C equivalent",
+                                name,
+                                self.type_name(self.compile_basic_type(unsupported.clone())?)
+                            ),
+                            unsupported.span(),
+                        ));
+                    }
+                }
+
+                let function = self.module.add_function(&name, fn_type, None);
+
+                if is_unsigned_type(return_type) {
+                    self.unsigned_returns.borrow_mut().insert(name.clone());
+                }
+
+                // main and exported functions are called from C so they have to use the C
+                // calling convention
+                if name != "main" && !exported {
+                    function.set_call_conventions(self.internal_call_conv());
+                }
+
+                if *exported {
+                    self.exported.borrow_mut().insert(name.clone());
+                    self.add_int_extensions(function, return_type, args_vec);
+
+                    if self.is_wasm_target() {
+                        function.add_attribute(
+                            AttributeLoc::Function,
+                            self.context
+                                .create_string_attribute("wasm-export-name", &name),
+                        );
+                    }
+                }
+
+                Ok(function)
+            }
+            // Only functions are declared, see `compile_ast` and `declare_constants`
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the type of a function, functions returning ```Unit``` return ```void```
+    fn function_type(
+        &self,
+        return_type: &AstNode,
+        param_types: &[BasicMetadataTypeEnum<'ctx>],
+        variadic: bool,
+    ) -> Result<FunctionType<'ctx>, FireworkError> {
+        Ok(if is_unit_type(return_type) {
+            self.context.void_type().fn_type(param_types, variadic)
+        } else {
+            self.compile_basic_type(return_type.clone())?
+                .fn_type(param_types, variadic)
+        })
+    }
+
+    /// Adds a function implemented in another language to the module
+    ///
+    /// Its symbol isn't mangled, so functions from libc or other libraries linked with the
+    /// program can be called
+    fn declare_extern(&self, node: &AstNode) -> Result<FunctionValue<'ctx>, FireworkError> {
+        match node {
+            AstNode::ExternFn {
+                abi,
+                name,
+                args,
+                return_type,
+                variadic,
+                span,
+            } => {
+                if abi != "C" {
+                    return Err(type_error(
+                        format!(
+                            "Unsupported ABI `{}` for `{}`, only \"C\" is supported",
+                            abi, name
+                        ),
+                        *span,
+                    ));
+                }
+
+                let args = match &**args {
+                    AstNode::FnArgs(args, _) => args,
+                    _ => unreachable!(),
+                };
+                let param_types = args
+                    .iter()
+                    .map(|(_, param_type)| self.compile_type(param_type.clone()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let fn_type = self.function_type(return_type, &param_types, *variadic)?;
+
+                // The libc declarations and the WASI runtime already provide some functions, like
+                // `puts` and `exit`, declaring them again with the same type refers to those
+                let function = match self.get_function(name) {
+                    Some(function)
+                        if function.get_type() != fn_type
+                            || function.get_call_conventions() == self.internal_call_conv() =>
+                    {
+                        return Err(type_error(
+                            format!("`{}` is already declared with a different type", name),
+                            *span,
+                        ));
+                    }
+                    Some(function) => function,
+                    None => self
+                        .module
+                        .add_function(name, fn_type, Some(Linkage::External)),
+                };
+
+                self.add_int_extensions(function, return_type, args);
+
+                if is_unsigned_type(return_type) {
+                    self.unsigned_returns.borrow_mut().insert(name.clone());
+                }
+
+                Ok(function)
+            }
+            // Only `extern` declarations are declared, see `compile_ast`
+            _ => unreachable!(),
+        }
+    }
+
+    /// Marks the integer parameters and return value of a function called from or implemented
+    /// in C as sign or zero extended, the C ABI of some targets requires them to be extended
+    /// to a whole register
+    fn add_int_extensions(
+        &self,
+        function: FunctionValue<'ctx>,
+        return_type: &AstNode,
+        args: &[(AstNode, AstNode)],
+    ) {
+        let params = args
+            .iter()
+            .enumerate()
+            .map(|(index, (_, param_type))| (AttributeLoc::Param(index as u32), param_type));
+
+        for (location, node_type) in
+            std::iter::once((AttributeLoc::Return, return_type)).chain(params)
+        {
+            if let Some(extension) = self.int_extension(node_type) {
+                function.add_attribute(
+                    location,
+                    self.context
+                        .create_enum_attribute(Attribute::get_named_enum_kind_id(extension), 0),
+                );
+            }
+        }
+    }
+
+    /// Returns how the C ABI extends integers of a type narrower than a register
+    fn int_extension(&self, node_type: &AstNode) -> Option<&'static str> {
+        match node_type {
+            AstNode::Type(typ, _) => match &typ[..] {
+                "bool" | "u8" | "u16" => Some("zeroext"),
+                "i8" | "i16" => Some("signext"),
+                // The RISC-V ABIs sign extend 32-bit integers whatever their signedness, other
+                // targets leave the upper bits of their registers undefined
+                "i32" | "u32" if self.target_arch().starts_with("riscv") => Some("signext"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Compiles the body of a function that has already been declared
+    fn compile_function(&self, node: AstNode) -> Result<FunctionValue<'ctx>, FireworkError> {
+        match node {
+            AstNode::Fn {
+                name,
+                return_type,
+                args,
+                value,
+                span,
+                ..
+            } => {
+                let args_vec: Vec<(AstNode, AstNode)> = match *args {
+                    AstNode::FnArgs(x, _) => x,
+                    _ => unreachable!(),
+                };
+
+                let source_name = name;
+                let name = self.scope.borrow().definition_symbol(&source_name);
+
+                let function = self.get_function(&name).unwrap();
+                let basic_block = self.context.append_basic_block(function, "entry");
+
+                self.builder.position_at_end(basic_block);
+
+                if self.debug_info.borrow().is_some() {
+                    self.describe_function(
+                        function,
+                        &source_name,
+                        &span.location(),
+                        &return_type,
+                        &args_vec,
+                    );
+                }
+
+                let mut variables = self.variables.borrow_mut();
+                variables.clear();
+
+                for (param, (arg, param_type)) in
+                    function.get_params().into_iter().zip(args_vec.iter())
+                {
+                    let name = match arg {
+                        AstNode::Identifier { name, .. } => name,
+                        _ => unreachable!(),
+                    };
+
+                    if let (BasicValueEnum::IntValue(param), true) =
+                        (param, is_unsigned_type(param_type))
+                    {
+                        self.unsigned_values.borrow_mut().insert(param);
+                    }
+
+                    variables.insert(name.clone(), param);
+                }
+
+                drop(variables);
+
+                if self.lazy_constants.borrow().contains(&name) {
+                    self.compile_lazy_constant(function, *value)?;
+                } else if name == "main" && is_unit_type(&return_type) {
+                    // The value of a `Unit` main is discarded, so the program exits successfully
+                    self.compile_astnode(*value)?;
+                    self.build_function_return(self.unit_value().into(), span)?;
+                } else {
+                    self.with_expected_type(function.get_type().get_return_type(), || {
+                        self.compile_tail(*value)
+                    })?;
+                }
+
+                if let Some(debug_info) = &*self.debug_info.borrow() {
+                    debug_info.end_function(&self.builder);
+                }
+
+                Ok(function)
+            }
+            // Called from `compile_astnode` on functions only
+            _ => unreachable!(),
+        }
+    }
+
+    /// Attaches the debug information of a function whose entry block the builder is
+    /// positioned at, its parameters are spilled to the stack so debuggers can show them
+    fn describe_function(
+        &self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        location: &Location,
+        return_type: &AstNode,
+        args: &[(AstNode, AstNode)],
+    ) {
+        let debug_info = self.debug_info.borrow();
+        let debug_info = debug_info.as_ref().unwrap();
+
+        let debug_type = |type_node: &AstNode, value_type: BasicTypeEnum<'ctx>| {
+            let type_name = match type_node {
+                // main returns the program's exit code instead of its declared type
+                AstNode::Type(type_name, _) if name != "main" => type_name.clone(),
+                _ => self.type_name(value_type),
+            };
+
+            debug_info.debug_type(&type_name, value_type)
+        };
+
+        let param_types = function
+            .get_params()
+            .into_iter()
+            .zip(args)
+            .map(|(param, (_, type_node))| debug_type(type_node, param.get_type()))
+            .collect::<Vec<_>>();
+        let debug_return_type = function
+            .get_type()
+            .get_return_type()
+            .filter(|_| !is_unit_type(return_type))
+            .map(|value_type| debug_type(return_type, value_type));
+
+        debug_info.begin_function(function, name, location, debug_return_type, &param_types);
+        debug_info.set_location(&self.builder, location);
+
+        for (arg_no, ((param, (arg, _)), param_type)) in function
+            .get_params()
+            .into_iter()
+            .zip(args)
+            .zip(param_types)
+            .enumerate()
+        {
+            let (name, location) = match arg {
+                AstNode::Identifier { name, span, .. } => (name, span.location()),
+                _ => unreachable!(),
+            };
+
+            let storage = self.builder.build_alloca(param.get_type(), name);
+            self.builder.build_store(storage, param);
+
+            debug_info.declare_parameter(
+                &self.builder,
+                storage,
+                name,
+                arg_no as u32 + 1,
+                &location,
+                param_type,
+            );
+        }
+    }
+
+    /// Returns the symbol a name refers to, errors point at ```span```
+    fn resolve(&self, name: &str, span: Span) -> Result<String, FireworkError> {
+        self.scope
+            .borrow()
+            .resolve(name)
+            .map_err(|diagnostic| (*diagnostic).with_span(span).into())
+    }
+
+    /// Reports a warning about the compiled code
+    fn warn(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    /// Returns the warnings reported since the last call
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.take()
+    }
+
+    /// Sets the source location of the instructions built next, if debug information is
+    /// emitted
+    fn set_debug_location(&self, location: &Location) {
+        if let Some(debug_info) = &*self.debug_info.borrow() {
+            debug_info.set_location(&self.builder, location);
+        }
+    }
+
+    /// Adds a function operating on integers to the core
+    fn add_core_function(
+        &self,
+        name: &str,
+        param_types: &[IntType<'ctx>],
+        return_type: IntType<'ctx>,
+        build_body: impl Fn(&[IntValue<'ctx>]) -> IntValue<'ctx>,
+    ) {
+        let func_type = return_type.fn_type(
+            &param_types
+                .iter()
+                .map(|&param_type| param_type.into())
+                .collect::<Vec<_>>(),
+            false,
+        );
+        let func = self.core.add_function(name, func_type, None);
+
+        self.builder
+            .position_at_end(self.context.append_basic_block(func, "entry"));
+
+        let params = func
+            .get_param_iter()
+            .map(|param| param.into_int_value())
+            .collect::<Vec<_>>();
+
+        self.builder.build_return(Some(&build_body(&params)));
+    }
+
+    /// Compiles ```&&``` and ```||```, which only evaluate their second operand if the first
+    /// one doesn't determine the result
+    fn compile_short_circuit(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<IntValue<'ctx>, FireworkError> {
+        let [lhs, rhs] = two_args(name, args, span)?;
+
+        let lhs = self.compile_condition(lhs)?;
+        let lhs_bb = self.builder.get_insert_block().unwrap();
+
+        let current_function = self.current_function();
+        let rhs_bb = self.context.append_basic_block(current_function, "rhs");
+        let cont_bb = self
+            .context
+            .append_basic_block(current_function, "logicalcont");
+
+        if name == "&&" {
+            self.builder.build_conditional_branch(lhs, rhs_bb, cont_bb);
+        } else {
+            self.builder.build_conditional_branch(lhs, cont_bb, rhs_bb);
+        }
+
+        self.builder.position_at_end(rhs_bb);
+        let rhs = self.compile_condition(rhs)?;
+        self.builder.build_unconditional_branch(cont_bb);
+        let rhs_bb = self.builder.get_insert_block().unwrap();
+
+        self.builder.position_at_end(cont_bb);
+
+        // The first operand is the result whenever the second one isn't evaluated
+        let phi = self
+            .builder
+            .build_phi(self.context.bool_type(), "logicaltmp");
+        phi.add_incoming(&[(&lhs, lhs_bb), (&rhs, rhs_bb)]);
+
+        Ok(phi.as_basic_value().into_int_value())
+    }
+
+    /// Checks whether a symbol refers to one of the ```SUM_HELPERS```, rather than to a
+    /// function of the program with the same name
+    fn is_sum_helper(&self, symbol: &str) -> bool {
+        SUM_HELPERS.contains(&symbol) && self.get_function(symbol).is_none()
+    }
+
+    /// Checks whether an operator is compiled with runtime checks at the call site
+    fn is_checked_operator(&self, symbol: &str) -> bool {
+        self.overflow_checks && CHECKED_OPERATORS.contains(&symbol)
+    }
+
+    /// Compiles the operands of an operator working on two integers, returns them converted to
+    /// the same type and whether they're unsigned
+    ///
+    /// Operands of different sizes are converted to the larger one, they're unsigned if either
+    /// operand is
+    fn compile_int_operands(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<(IntValue<'ctx>, IntValue<'ctx>, bool), FireworkError> {
+        let [lhs, rhs] = two_args(name, args, span)?;
+
+        // An integer literal takes the type of the other operand
+        let (lhs, rhs) = if matches!(lhs, AstNode::Int(..)) && !matches!(rhs, AstNode::Int(..)) {
+            let rhs = self.compile_int(name, rhs)?;
+            let lhs = self
+                .with_expected_type(Some(rhs.get_type().into()), || self.compile_int(name, lhs))?;
+
+            (lhs, rhs)
+        } else {
+            let lhs = self.compile_int(name, lhs)?;
+            let rhs = self
+                .with_expected_type(Some(lhs.get_type().into()), || self.compile_int(name, rhs))?;
+
+            (lhs, rhs)
+        };
+
+        let is_unsigned = {
+            let unsigned_values = self.unsigned_values.borrow();
+            unsigned_values.contains(&lhs) || unsigned_values.contains(&rhs)
+        };
+        let int_type = if lhs.get_type().get_bit_width() >= rhs.get_type().get_bit_width() {
+            lhs.get_type()
+        } else {
+            rhs.get_type()
+        };
+
+        Ok((
+            self.coerce_int(lhs, int_type),
+            self.coerce_int(rhs, int_type),
+            is_unsigned,
+        ))
+    }
+
+    /// Compiles a comparison of two integers, they're compared as unsigned integers if either
+    /// one is unsigned
+    fn compile_comparison(
+        &self,
+        name: &str,
+        (signed, unsigned): (IntPredicate, IntPredicate),
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<IntValue<'ctx>, FireworkError> {
+        let (lhs, rhs, is_unsigned) = self.compile_int_operands(name, args, span)?;
+        self.set_debug_location(&span.location());
+
+        let predicate = if is_unsigned { unsigned } else { signed };
+
+        Ok(self.builder.build_int_compare(predicate, lhs, rhs, "cmp"))
+    }
+
+    /// Compiles an arithmetic operator that panics instead of overflowing or dividing by zero
+    fn compile_checked_arithmetic(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<IntValue<'ctx>, FireworkError> {
+        let location = span.location();
+        let (lhs, rhs, is_unsigned) = self.compile_int_operands(name, args, span)?;
+        let int_type = lhs.get_type();
+        let width = int_type.get_bit_width();
+        self.set_debug_location(&location);
+
+        let result = match name {
+            "/" | "%" => {
+                let (operation, zero_message) = if name == "/" {
+                    ("divide", "attempt to divide by zero")
+                } else {
+                    (
+                        "calculate the remainder",
+                        "attempt to calculate the remainder with a divisor of zero",
+                    )
+                };
+
+                let is_zero = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    rhs,
+                    int_type.const_zero(),
+                    "iszero",
+                );
+                self.build_panic_if(is_zero, zero_message, location);
+
+                // The minimum of a signed type divided by -1 doesn't fit in the type
+                if !is_unsigned {
+                    let is_min = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        lhs,
+                        int_type.const_int(1 << (width - 1), false),
+                        "ismin",
+                    );
+                    let is_minus_one = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        rhs,
+                        int_type.const_all_ones(),
+                        "isminusone",
+                    );
+                    let overflows = self.builder.build_and(is_min, is_minus_one, "overflows");
+                    self.build_panic_if(
+                        overflows,
+                        &format!("attempt to {} with overflow", operation),
+                        location,
+                    );
+                }
+
+                match (name, is_unsigned) {
+                    ("/", false) => self.builder.build_int_signed_div(lhs, rhs, "div"),
+                    ("/", true) => self.builder.build_int_unsigned_div(lhs, rhs, "div"),
+                    (_, false) => self.builder.build_int_signed_rem(lhs, rhs, "rem"),
+                    (_, true) => self.builder.build_int_unsigned_rem(lhs, rhs, "rem"),
+                }
+            }
+            "<<" | ">>" => {
+                let direction = if name == "<<" { "left" } else { "right" };
+
+                // Negative shift amounts are out of range too once compared as unsigned
+                let out_of_range = self.builder.build_int_compare(
+                    IntPredicate::UGE,
+                    rhs,
+                    int_type.const_int(u64::from(width), false),
+                    "outofrange",
+                );
+                self.build_panic_if(
+                    out_of_range,
+                    &format!("attempt to shift {} with overflow", direction),
+                    location,
+                );
+
+                if name == "<<" {
+                    self.builder.build_left_shift(lhs, rhs, "shl")
+                } else {
+                    self.builder
+                        .build_right_shift(lhs, rhs, !is_unsigned, "shr")
+                }
+            }
+            _ => {
+                let (instruction, operation) = match name {
+                    "+" => ("add", "add"),
+                    "-" => ("sub", "subtract"),
+                    _ => ("mul", "multiply"),
+                };
+                let intrinsic = format!(
+                    "llvm.{}{}.with.overflow.i{}",
+                    if is_unsigned { "u" } else { "s" },
+                    instruction,
+                    width
+                );
+
+                let function = self.get_function(&intrinsic).unwrap_or_else(|| {
+                    let return_type = self
+                        .context
+                        .struct_type(&[int_type.into(), self.context.bool_type().into()], false);
+
+                    self.module.add_function(
+                        &intrinsic,
+                        return_type.fn_type(&[int_type.into(), int_type.into()], false),
+                        None,
+                    )
+                });
+
+                let result = self
+                    .builder
+                    .build_call(function, &[lhs.into(), rhs.into()], "checked")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_struct_value();
+
+                let overflowed = self
+                    .builder
+                    .build_extract_value(result, 1, "overflowed")
+                    .unwrap()
+                    .into_int_value();
+                self.build_panic_if(
+                    overflowed,
+                    &format!("attempt to {} with overflow", operation),
+                    location,
+                );
+
+                self.builder
+                    .build_extract_value(result, 0, "result")
+                    .unwrap()
+                    .into_int_value()
+            }
+        };
+
+        if is_unsigned {
+            self.unsigned_values.borrow_mut().insert(result);
+        }
+
+        Ok(result)
+    }
+
+    /// Compiles an operand of an operator that only works on integers
+    fn compile_int(&self, name: &str, node: AstNode) -> Result<IntValue<'ctx>, FireworkError> {
+        let span = node.span();
+
+        match self.compile_astnode(node)? {
+            AnyValueEnum::IntValue(int) if int.get_type().get_bit_width() > 1 => Ok(int),
+            _ => Err(type_error(
+                format!("The operands of `{}` must be integers", name),
+                span,
+            )),
+        }
+    }
+
+    /// Panics if the condition is true, otherwise continues in a new basic block
+    fn build_panic_if(&self, condition: IntValue<'ctx>, message: &str, location: Location) {
+        let current_function = self.current_function();
+        let panic_bb = self.context.append_basic_block(current_function, "panic");
+        let cont_bb = self.context.append_basic_block(current_function, "checked");
+
+        self.builder
+            .build_conditional_branch(condition, panic_bb, cont_bb);
+
+        self.builder.position_at_end(panic_bb);
+        self.build_panic(self.build_string(message), location);
+
+        self.builder.position_at_end(cont_bb);
+    }
+
+    /// Compiles a call to one of the ```BUILTINS```
+    fn compile_builtin(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<AnyValueEnum<'ctx>, FireworkError> {
+        if CONSTRUCTORS.contains(&name) {
+            return Ok(self.compile_constructor(name, args, span)?.into());
+        }
+
+        let current_function = self.current_function();
+        let location = span.location();
+
+        match (name, <[AstNode; 2]>::try_from(args)) {
+            ("assert", Ok([condition, message])) => {
+                let condition = self.compile_condition(condition)?;
+
+                let panic_bb = self.context.append_basic_block(current_function, "panic");
+                let cont_bb = self
+                    .context
+                    .append_basic_block(current_function, "assertcont");
+
+                self.builder
+                    .build_conditional_branch(condition, cont_bb, panic_bb);
+
+                // The message is only evaluated if the assertion fails
+                self.builder.position_at_end(panic_bb);
+                let message = self.compile_panic_message(name, message)?;
+                self.build_panic(message, location);
+
+                self.builder.position_at_end(cont_bb);
+            }
+            ("panic", Err(args)) if args.len() == 1 => {
+                let message = self.compile_panic_message(name, args.into_iter().next().unwrap())?;
+                self.build_panic(message, location);
+
+                // Code following a panic is never executed
+                let dead_bb = self
+                    .context
+                    .append_basic_block(current_function, "afterpanic");
+                self.builder.position_at_end(dead_bb);
+            }
+            ("assert", _) => {
+                return Err(type_error("`assert` takes a condition and a message", span))
+            }
+            _ => return Err(type_error("`panic` takes exactly 1 argument", span)),
+        }
+
+        Ok(self.unit_value().into())
+    }
+
+    fn compile_panic_message(
+        &self,
+        name: &str,
+        message: AstNode,
+    ) -> Result<PointerValue<'ctx>, FireworkError> {
+        let span = message.span();
+
+        match self.compile_astnode(message)? {
+            AnyValueEnum::PointerValue(message) => Ok(message),
+            _ => Err(type_error(
+                format!("The message of `{}` must be a `str`", name),
+                span,
+            )),
+        }
+    }
+
+    /// Checks whether the builder is inserting into a block that can never be reached, like
+    /// the code following a call to ```panic```
+    fn is_unreachable(&self) -> bool {
+        let block = self.builder.get_insert_block().unwrap();
+
+        block.get_first_use().is_none()
+            && self.current_function().get_first_basic_block() != Some(block)
+    }
+
+    /// Calls the panic routine with the source location of the code that panicked
+    fn build_panic(&self, message: PointerValue<'ctx>, location: Location) {
+        let i64_type = self.context.i64_type();
+        let file = self.build_string(&self.source_path.borrow());
+
+        self.builder.build_call(
+            self.get_function(PANIC_FUNCTION).unwrap(),
+            &[
+                message.into(),
+                file.into(),
+                i64_type.const_int(location.line as u64, false).into(),
+                i64_type.const_int(location.column as u64, false).into(),
+            ],
+            "",
+        );
+        self.builder.build_unreachable();
+    }
+
+    /// Compiles an expression with a known expected type, which is used to infer the parts of
+    /// a type that constructors like ```None``` don't determine
+    fn with_expected_type<T>(
+        &self,
+        expected_type: Option<BasicTypeEnum<'ctx>>,
+        compile: impl FnOnce() -> T,
+    ) -> T {
+        let outer = self.expected_type.replace(expected_type);
+        let result = compile();
+        *self.expected_type.borrow_mut() = outer;
+
+        result
+    }
+
+    /// Returns the type a branch of an if statement or a match is expected to have, which is
+    /// the type of the branches compiled before it if nothing else is expected
+    fn branch_type(
+        &self,
+        compiled: &[(BasicValueEnum<'ctx>, BasicBlock<'ctx>)],
+    ) -> Option<BasicTypeEnum<'ctx>> {
+        self.expected_type
+            .borrow()
+            .or_else(|| compiled.first().map(|(value, _)| value.get_type()))
+    }
+
+    /// Returns how a type is written in Firework
+    fn type_name(&self, value_type: BasicTypeEnum<'ctx>) -> String {
+        match value_type {
+            BasicTypeEnum::IntType(int) if int.get_bit_width() == 1 => "bool".to_string(),
+            BasicTypeEnum::IntType(int) => format!("i{}", int.get_bit_width()),
+            BasicTypeEnum::FloatType(_) => "f64".to_string(),
+            BasicTypeEnum::PointerType(pointer) => match pointer.get_element_type() {
+                AnyTypeEnum::IntType(int) if int.get_bit_width() == 8 => "str".to_string(),
+                pointee => match BasicTypeEnum::try_from(pointee) {
+                    Ok(pointee) => format!("*{}", self.type_name(pointee)),
+                    Err(_) => pointee.print_to_string().to_string(),
+                },
+            },
+            BasicTypeEnum::StructType(struct_type) => match struct_type.get_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "Unit".to_string(),
+            },
+            other => other.print_to_string().to_string(),
+        }
+    }
+
+    /// Returns the struct representing ```Option``` or ```Result``` applied to type arguments
+    ///
+    /// The struct is named after the Firework type, e.g. ```Result i64 str```
+    fn sum_type(&self, sum_type: SumType, args: &[BasicTypeEnum<'ctx>]) -> StructType<'ctx> {
+        let name = std::iter::once(format!("{:?}", sum_type))
+            .chain(args.iter().map(|&arg| {
+                let name = self.type_name(arg);
+
+                // Type arguments taking type arguments themselves are wrapped in parentheses
+                if name.contains(' ') {
+                    format!("({})", name)
+                } else {
+                    name
+                }
+            }))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.module.get_struct_type(&name).unwrap_or_else(|| {
+            let struct_type = self.context.opaque_struct_type(&name);
+            let fields = std::iter::once(self.context.bool_type().as_basic_type_enum())
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>();
+            struct_type.set_body(&fields, false);
+
+            struct_type
+        })
+    }
+
+    /// Returns which builtin sum type a type is, if it is one
+    fn as_sum_type(&self, value_type: BasicTypeEnum<'ctx>) -> Option<(SumType, StructType<'ctx>)> {
+        let struct_type = match value_type {
+            BasicTypeEnum::StructType(struct_type) => struct_type,
+            _ => return None,
+        };
+        let name = struct_type.get_name()?.to_string_lossy();
+
+        if name.starts_with("Option ") {
+            Some((SumType::Option, struct_type))
+        } else if name.starts_with("Result ") {
+            Some((SumType::Result, struct_type))
+        } else {
+            None
+        }
+    }
+
+    /// Builds a value of a sum type, setting the payload at the given field if there is one
+    fn build_sum_value(
+        &self,
+        struct_type: StructType<'ctx>,
+        is_present: bool,
+        payload: Option<(u32, BasicValueEnum<'ctx>)>,
+    ) -> StructValue<'ctx> {
+        let tag = self.context.bool_type().const_int(is_present as u64, false);
+        let mut value = self
+            .builder
+            .build_insert_value(struct_type.const_zero(), tag, 0, "tag")
+            .unwrap();
+
+        if let Some((index, payload)) = payload {
+            value = self
+                .builder
+                .build_insert_value(value, payload, index, "payload")
+                .unwrap();
+        }
+
+        value.into_struct_value()
+    }
+
+    /// Compiles ```Some```, ```None```, ```Ok``` and ```Err```
+    ///
+    /// Parts of the type that the arguments don't determine are taken from the expected type
+    fn compile_constructor(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<StructValue<'ctx>, FireworkError> {
+        let (sum_type, is_present, index) = match name {
+            "Some" => (SumType::Option, true, 1),
+            "None" => (SumType::Option, false, 1),
+            "Ok" => (SumType::Result, true, 1),
+            _ => (SumType::Result, false, 2),
+        };
+
+        let expected_type = (*self.expected_type.borrow())
+            .and_then(|expected_type| self.as_sum_type(expected_type))
+            .filter(|(expected_sum_type, _)| *expected_sum_type == sum_type)
+            .map(|(_, struct_type)| struct_type);
+
+        let arg = match (name, <[AstNode; 1]>::try_from(args)) {
+            ("None", Err(args)) if args.is_empty() => None,
+            ("None", _) => return Err(type_error("`None` doesn't take any arguments", span)),
+            (_, Ok([arg])) => Some(arg),
+            _ => {
+                return Err(type_error(
+                    format!("`{}` takes exactly 1 argument", name),
+                    span,
+                ))
+            }
+        };
+
+        let payload = match arg {
+            Some(arg) => {
+                let payload_type = expected_type
+                    .and_then(|struct_type| struct_type.get_field_type_at_index(index));
+
+                Some(any_value_enum_to_basic_value_enum!(self
+                    .with_expected_type(payload_type, || self
+                        .compile_astnode(arg))?))
+            }
+            None => None,
+        };
+
+        let struct_type = match (expected_type, payload) {
+            (Some(struct_type), _) => struct_type,
+            (None, Some(payload)) if sum_type == SumType::Option => {
+                self.sum_type(sum_type, &[payload.get_type()])
+            }
+            _ if self.expected_type.borrow().is_some() => {
+                return Err(type_error(
+                    format!(
+                    "Mismatched types, `{}` builds a value of type `{:?}` where `{}` is expected",
+                    name,
+                    sum_type,
+                    self.type_name(self.expected_type.borrow().unwrap())
+                ),
+                    span,
+                ))
+            }
+            _ => {
+                return Err(type_error(
+                    format!(
+                    "Can't infer the type of `{}`, annotate the type it's bound to or returned as",
+                    name
+                ),
+                    span,
+                ))
+            }
+        };
+
+        if let Some(payload) = payload {
+            if struct_type.get_field_type_at_index(index) != Some(payload.get_type()) {
+                return Err(type_error(
+                    format!(
+                        "Mismatched types, `{}` of `{}` is not of type `{}`",
+                        name,
+                        self.type_name(payload.get_type()),
+                        struct_type.get_name().unwrap().to_string_lossy()
+                    ),
+                    span,
+                ));
+            }
+        }
+
+        Ok(self.build_sum_value(
+            struct_type,
+            is_present,
+            payload.map(|payload| (index, payload)),
+        ))
+    }
+
+    /// Returns the function an argument of a builtin refers to
+    fn function_reference(
+        &self,
+        builtin: &str,
+        node: &AstNode,
+    ) -> Result<FunctionValue<'ctx>, FireworkError> {
+        match node {
+            AstNode::Identifier {
+                name, args, span, ..
+            } if args.is_empty() => self.get_function(&self.resolve(name, *span)?),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            type_error(
+                format!(
+                    "`{}` expects the name of a function as its second argument",
+                    builtin
+                ),
+                node.span(),
+            )
+        })
+    }
+
+    /// Compiles ```map```, ```and_then``` and ```unwrap_or```, which work on both ```Option```
+    /// and ```Result``` values
+    fn compile_sum_helper(
+        &self,
+        name: &str,
+        args: Vec<AstNode>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, FireworkError> {
+        let [value, arg] = two_args(name, args, span)?;
+        let value_span = value.span();
+
+        let value = any_value_enum_to_basic_value_enum!(
+            self.with_expected_type(None, || self.compile_astnode(value))?
+        );
+        let (sum_type, struct_type) = self.as_sum_type(value.get_type()).ok_or_else(|| {
+            type_error(
+                format!("`{}` expects an `Option` or a `Result`", name),
+                value_span,
+            )
+        })?;
+        let value = value.into_struct_value();
+
+        let is_present = self
+            .builder
+            .build_extract_value(value, 0, "is_present")
+            .unwrap()
+            .into_int_value();
+        let payload = self
+            .builder
+            .build_extract_value(value, 1, "payload")
+            .unwrap();
+
+        let arg_span = arg.span();
+
+        if name == "unwrap_or" {
+            let default =
+                any_value_enum_to_basic_value_enum!(self
+                    .with_expected_type(Some(payload.get_type()), || self.compile_astnode(arg))?);
+
+            if default.get_type() != payload.get_type() {
+                return Err(type_error(
+                    format!(
+                        "Mismatched types, the default value of `unwrap_or` is not of type `{}`",
+                        self.type_name(payload.get_type())
+                    ),
+                    arg_span,
+                ));
+            }
+
+            return Ok(self
+                .builder
+                .build_select(is_present, payload, default, "unwrapped"));
+        }
+
+        let function = self.function_reference(name, &arg)?;
+        let function_name = function.get_name().to_string_lossy().to_string();
+
+        if function.get_type().get_param_types() != [payload.get_type()] {
+            return Err(type_error(
+                format!(
+                    "`{}` has to take a single argument of type `{}` to be used with `{}`",
+                    function_name,
+                    self.type_name(payload.get_type()),
+                    name
+                ),
+                arg_span,
+            ));
+        }
+
+        let return_type = function.get_type().get_return_type().ok_or_else(|| {
+            type_error(
+                format!(
+                    "`{}` returns `Unit` and can't be used with `{}`",
+                    function_name, name
+                ),
+                arg_span,
+            )
+        })?;
+
+        // The error type is kept as is, only the value gets transformed
+        let error_type = struct_type.get_field_type_at_index(2);
+
+        let result_type = if name == "map" {
+            let args = std::iter::once(return_type)
+                .chain(error_type)
+                .collect::<Vec<_>>();
+            self.sum_type(sum_type, &args)
+        } else {
+            match self.as_sum_type(return_type) {
+                Some((returned_sum_type, result_type))
+                    if returned_sum_type == sum_type
+                        && result_type.get_field_type_at_index(2) == error_type =>
+                {
+                    result_type
+                }
+                _ => {
+                    return Err(type_error(
+                        format!(
+                            "`{}` has to return {} to be used with `and_then`",
+                            function_name,
+                            match sum_type {
+                                SumType::Option => "an `Option`",
+                                SumType::Result => "a `Result` with the same error type",
+                            }
+                        ),
+                        arg_span,
+                    ))
+                }
+            }
+        };
+
+        let current_function = self.current_function();
+        let present_bb = self.context.append_basic_block(current_function, "present");
+        let absent_bb = self.context.append_basic_block(current_function, "absent");
+        let cont_bb = self.context.append_basic_block(current_function, "sumcont");
+
+        self.builder
+            .build_conditional_branch(is_present, present_bb, absent_bb);
+
+        self.builder.position_at_end(present_bb);
+        let called = self.builder.build_call(function, &[payload.into()], name);
+        called.set_call_convention(function.get_call_conventions());
+        let called = called.try_as_basic_value().left().unwrap();
+        let present = if name == "map" {
+            self.build_sum_value(result_type, true, Some((1, called)))
+        } else {
+            called.into_struct_value()
+        };
+        self.builder.build_unconditional_branch(cont_bb);
+        let present_bb = self.builder.get_insert_block().unwrap();
+
+        self.builder.position_at_end(absent_bb);
+        let absent = match sum_type {
+            SumType::Option => self.build_sum_value(result_type, false, None),
+            SumType::Result => {
+                let error = self.builder.build_extract_value(value, 2, "error").unwrap();
+                self.build_sum_value(result_type, false, Some((2, error)))
+            }
+        };
+        self.builder.build_unconditional_branch(cont_bb);
+
+        self.builder.position_at_end(cont_bb);
+        let phi = self.builder.build_phi(result_type, name);
+        phi.add_incoming(&[(&present, present_bb), (&absent, absent_bb)]);
+
+        Ok(phi.as_basic_value())
+    }
+
+    /// Compiles a match expression on an ```Option``` or a ```Result```
+    ///
+    /// The first arm whose pattern matches a constructor handles it. In tail position every
+    /// arm is compiled as a tail expression and no value is returned.
+    fn compile_match(
+        &self,
+        value: AstNode,
+        arms: Vec<(AstNode, AstNode)>,
+        tail: bool,
+        span: Span,
+    ) -> Result<Option<AnyValueEnum<'ctx>>, FireworkError> {
+        let value_span = value.span();
+        let value = any_value_enum_to_basic_value_enum!(
+            self.with_expected_type(None, || self.compile_astnode(value))?
+        );
+        let (sum_type, struct_type) = self.as_sum_type(value.get_type()).ok_or_else(|| {
+            type_error(
+                format!(
+                    "Can't match on a value of type `{}`, only `Option` and `Result` values can be matched on",
+                    self.type_name(value.get_type())
+                ),
+                value_span,
+            )
+        })?;
+        let value = value.into_struct_value();
+        let type_name = struct_type
+            .get_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+
+        let (present, absent) = match sum_type {
+            SumType::Option => ("Some", "None"),
+            SumType::Result => ("Ok", "Err"),
+        };
+
+        for (pattern, _) in &arms {
+            if let AstNode::Pattern {
+                constructor,
+                binding,
+                span,
+            } = pattern
+            {
+                let is_constructor = CONSTRUCTORS.contains(&constructor.as_str());
+
+                if is_constructor && constructor != present && constructor != absent {
+                    return Err(type_error(
+                        format!(
+                            "`{}` can't match a value of type `{}`",
+                            constructor, type_name
+                        ),
+                        *span,
+                    ));
+                }
+
+                if binding.is_some() && (!is_constructor || constructor == "None") {
+                    return Err(type_error(
+                        format!("`{}` doesn't hold a value", constructor),
+                        *span,
+                    ));
+                }
+            }
+        }
+
+        let arm_for = |constructor: &str| {
+            arms.iter()
+                .position(|(pattern, _)| match pattern {
+                    AstNode::Pattern {
+                        constructor: pattern,
+                        binding,
+                        ..
+                    } => {
+                        pattern == constructor
+                            || (binding.is_none() && !CONSTRUCTORS.contains(&pattern.as_str()))
+                    }
+                    _ => unreachable!(),
+                })
+                .ok_or_else(|| {
+                    type_error(
+                        format!(
+                            "Non-exhaustive match on `{}`, `{}` isn't covered",
+                            type_name, constructor
+                        ),
+                        span,
+                    )
+                })
+        };
+        let present_arm = arm_for(present)?;
+        let absent_arm = arm_for(absent)?;
+
+        if let Some(unreachable_arm) =
+            (0..arms.len()).find(|&arm| arm != present_arm && arm != absent_arm)
+        {
+            self.warn(
+                Diagnostic::warning(format!("Unreachable pattern in a match on `{}`", type_name))
+                    .with_span(arms[unreachable_arm].0.span())
+                    .with_code("unreachable-pattern")
+                    .with_label("unreachable pattern")
+                    .with_note("only the first pattern matching each constructor is used"),
+            );
+        }
+
+        let current_function = self.current_function();
+        let tag = self
+            .builder
+            .build_extract_value(value, 0, "is_present")
+            .unwrap()
+            .into_int_value();
+
+        let mut used_arms = vec![present_arm, absent_arm];
+        used_arms.dedup();
+        let blocks = used_arms
+            .iter()
+            .map(|&arm| {
+                (
+                    arm,
+                    self.context.append_basic_block(current_function, "arm"),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        if present_arm == absent_arm {
+            self.builder
+                .build_unconditional_branch(blocks[&present_arm]);
+        } else {
+            self.builder
+                .build_conditional_branch(tag, blocks[&present_arm], blocks[&absent_arm]);
+        }
+
+        let cont_bb = self
+            .context
+            .append_basic_block(current_function, "matchcont");
+        let outer_scope = self.variables.borrow().clone();
+        let mut incoming = vec![];
+
+        for (index, (pattern, expr)) in arms.into_iter().enumerate() {
+            let block = match blocks.get(&index) {
+                Some(&block) => block,
+                None => continue,
+            };
+            self.builder.position_at_end(block);
+
+            if let AstNode::Pattern {
+                constructor,
+                binding,
+                ..
+            } = pattern
+            {
+                let bound = match (binding, constructor.as_str()) {
+                    (Some(binding), "Err") => Some((binding, 2)),
+                    (Some(binding), _) => Some((binding, 1)),
+                    (None, "_") => None,
+                    (None, constructor) if !CONSTRUCTORS.contains(&constructor) => {
+                        self.variables
+                            .borrow_mut()
+                            .insert(constructor.to_string(), value.into());
+                        None
+                    }
+                    (None, _) => None,
+                };
+
+                if let Some((binding, field)) = bound {
+                    if binding != "_" {
+                        let payload = self
+                            .builder
+                            .build_extract_value(value, field, &binding)
+                            .unwrap();
+                        self.variables.borrow_mut().insert(binding, payload);
+                    }
+                }
+            }
+
+            if tail {
+                self.compile_tail(expr)?;
+            } else {
+                let value = self.with_expected_type(self.branch_type(&incoming), || {
+                    self.compile_astnode(expr)
+                })?;
+
+                if self.is_unreachable() {
+                    self.builder.build_unreachable();
+                } else {
+                    self.builder.build_unconditional_branch(cont_bb);
+                    incoming.push((
+                        any_value_enum_to_basic_value_enum!(value),
+                        self.builder.get_insert_block().unwrap(),
+                    ));
+                }
+            }
+
+            *self.variables.borrow_mut() = outer_scope.clone();
+        }
+
+        if tail {
+            cont_bb.remove_from_function().unwrap();
+            return Ok(None);
+        }
+
+        self.builder.position_at_end(cont_bb);
+
+        if incoming.is_empty() {
+            return Ok(Some(self.unit_value().into()));
+        }
+
+        let phi = self.builder.build_phi(incoming[0].0.get_type(), "matchtmp");
+        phi.add_incoming(
+            &incoming
+                .iter()
+                .map(|(value, block)| (value as &dyn BasicValue, *block))
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(Some(phi.as_basic_value().into()))
+    }
+
+    /// Compiles the ```?``` operator, which evaluates to the value of a ```Some``` or an
+    /// ```Ok``` and otherwise returns the ```None``` or the ```Err``` from the current function
+    fn compile_try(
+        &self,
+        node: AstNode,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, FireworkError> {
+        let value = any_value_enum_to_basic_value_enum!(
+            self.with_expected_type(None, || self.compile_astnode(node))?
+        );
+        let (sum_type, struct_type) = self.as_sum_type(value.get_type()).ok_or_else(|| {
+            type_error("`?` can only be applied to an `Option` or a `Result`", span)
+        })?;
+        let value = value.into_struct_value();
+
+        let current_function = self.current_function();
+        let return_type = current_function
+            .get_type()
+            .get_return_type()
+            .and_then(|return_type| self.as_sum_type(return_type))
+            .filter(|(returned_sum_type, _)| *returned_sum_type == sum_type)
+            .map(|(_, return_type)| return_type)
+            .ok_or_else(|| {
+                type_error(
+                    format!(
+                        "`?` can only be used on `{:?}` values in functions returning an `{:?}`",
+                        sum_type, sum_type
+                    ),
+                    span,
+                )
+            })?;
+
+        if return_type.get_field_type_at_index(2) != struct_type.get_field_type_at_index(2) {
+            return Err(type_error(
+                format!(
+                    "Mismatched error types, `?` can't return the error of `{}` from a function returning `{}`",
+                    struct_type.get_name().unwrap().to_string_lossy(),
+                    return_type.get_name().unwrap().to_string_lossy()
+                ),
+                span,
+            ));
+        }
+
+        let is_present = self
+            .builder
+            .build_extract_value(value, 0, "is_present")
+            .unwrap()
+            .into_int_value();
+
+        let present_bb = self.context.append_basic_block(current_function, "present");
+        let absent_bb = self.context.append_basic_block(current_function, "absent");
+
+        self.builder
+            .build_conditional_branch(is_present, present_bb, absent_bb);
+
+        self.builder.position_at_end(absent_bb);
+        let returned = match sum_type {
+            SumType::Option => self.build_sum_value(return_type, false, None),
+            SumType::Result => {
+                let error = self.builder.build_extract_value(value, 2, "error").unwrap();
+                self.build_sum_value(return_type, false, Some((2, error)))
+            }
+        };
+        self.builder.build_return(Some(&returned));
+
+        self.builder.position_at_end(present_bb);
+        Ok(self.builder.build_extract_value(value, 1, "value").unwrap())
+    }
+
+    /// Compiles a file's declarations
+    ///
+    /// Every function's prototype is added to the module before any body gets compiled, so
+    /// functions can call functions that are defined later in the file
+    fn compile_ast(&self, ast: &[AstNode]) -> Result<(), FireworkError> {
+        let (constants, functions): (Vec<_>, Vec<_>) = ast
+            .iter()
+            .filter(|node| matches!(node, AstNode::Fn { .. }))
+            .partition(|node| self.is_constant(node));
+
+        ast.iter()
+            .filter(|node| matches!(node, AstNode::Fn { .. }))
+            .for_each(|node| self.record_calls(node));
+
+        for node in ast {
+            if let AstNode::ExternFn { .. } = node {
+                self.declare_extern(node)?;
+            }
+        }
+
+        for node in functions {
+            self.declare_function(node)?;
+        }
+
+        self.declare_constants(constants)?;
+
+        for node in ast.iter().cloned() {
+            self.compile_astnode(node)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the functions a function's body may call to the call graph
+    fn record_calls(&self, node: &AstNode) {
+        if let AstNode::Fn {
+            name, args, value, ..
+        } = node
+        {
+            let mut locals = match &**args {
+                AstNode::FnArgs(args, _) => args
+                    .iter()
+                    .filter_map(|(arg, _)| match arg {
+                        AstNode::Identifier { name, .. } => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+                _ => unreachable!(),
+            };
+            locals.extend(let_bindings(value));
+
+            let mut called = HashSet::new();
+            self.called_functions(value, &locals, &mut called);
+
+            self.call_graph
+                .borrow_mut()
+                .insert(self.scope.borrow().definition_symbol(name), called);
+        }
+    }
+
+    /// Checks whether a declaration is a constant
+    ///
+    /// Constants are declarations without arguments that don't return `Unit`, except for main
+    /// and exported functions
+    fn is_constant(&self, node: &AstNode) -> bool {
+        match node {
+            AstNode::Fn {
+                name,
+                return_type,
+                args,
+                exported,
+                ..
+            } => {
+                matches!(&**args, AstNode::FnArgs(args, _) if args.is_empty())
+                    && !is_unit_type(return_type)
+                    && !exported
+                    && self.scope.borrow().definition_symbol(name) != "main"
+            }
+            _ => false,
+        }
+    }
+
+    /// Evaluates constants at compile time and adds them to the module as global constants
+    ///
+    /// Constants that can't be evaluated at compile time are compiled to functions that
+    /// evaluate them once, the first time they're used, as long as they don't have side effects
+    fn declare_constants(&self, mut constants: Vec<&AstNode>) -> Result<(), FireworkError> {
+        let mut evaluated = HashMap::new();
+        let declared_types = constants
+            .iter()
+            .map(|node| match node {
+                AstNode::Fn {
+                    name,
+                    return_type,
+                    value,
+                    ..
+                } => Ok((
+                    self.scope.borrow().definition_symbol(name),
+                    (self.compile_basic_type(*return_type.clone())?, value.span()),
+                )),
+                _ => unreachable!(),
+            })
+            .collect::<Result<HashMap<_, _>, FireworkError>>()?;
+
+        // Constants may refer to constants that are declared after them
+        loop {
+            let pending = constants.len();
+
+            constants.retain(|node| match node {
+                AstNode::Fn { name, value, .. } => {
+                    match self.evaluate_constant(value, &evaluated) {
+                        Some(constant) => {
+                            evaluated.insert(self.scope.borrow().definition_symbol(name), constant);
+                            false
+                        }
+                        None => true,
+                    }
+                }
+                _ => unreachable!(),
+            });
+
+            if constants.len() == pending {
+                break;
+            }
+        }
+
+        for (symbol, constant) in evaluated {
+            let (declared_type, span) = declared_types[&symbol];
+
+            // Integers are evaluated as i64 and take the declared size afterwards
+            let value = match (&constant, declared_type) {
+                (Constant::Int(int), BasicTypeEnum::IntType(int_type))
+                    if int_type.get_bit_width() > 1 =>
+                {
+                    if !fits_in(*int, int_type.get_bit_width()) {
+                        return Err(type_error(
+                            format!(
+                                "The value of constant `{}` doesn't fit in {} bits",
+                                symbol,
+                                int_type.get_bit_width()
+                            ),
+                            span,
+                        ));
+                    }
+
+                    int_type.const_int(*int as u64, true).into()
+                }
+                _ => self.constant_value(&constant),
+            };
+
+            if declared_type != value.get_type() {
+                return Err(type_error(
+                    format!(
+                        "Mismatched types, the value of constant `{}` doesn't match its type",
+                        symbol
+                    ),
+                    span,
+                ));
+            }
+
+            let global = self.module.add_global(value.get_type(), None, &symbol);
+            global.set_initializer(&value);
+            global.set_constant(true);
+
+            self.constants.borrow_mut().insert(symbol, value);
+        }
+
+        for node in constants {
+            let symbol = match node {
+                AstNode::Fn { name, .. } => self.scope.borrow().definition_symbol(name),
+                _ => unreachable!(),
+            };
+
+            if self.has_side_effects(&symbol) {
+                return Err(type_error(
+                    format!(
+                        "Constant `{}` has side effects, make it return `Unit` or take arguments instead",
+                        symbol
+                    ),
+                    node.span(),
+                ));
+            }
+
+            self.declare_function(node)?;
+            self.lazy_constants.borrow_mut().insert(symbol);
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates an expression at compile time, returns ```None``` if that isn't possible
+    fn evaluate_constant(
+        &self,
+        node: &AstNode,
+        constants: &HashMap<String, Constant>,
+    ) -> Option<Constant> {
+        match node {
+            AstNode::Int(int, _) => Some(Constant::Int(*int)),
+            AstNode::Float(float, _) => Some(Constant::Float(*float)),
+            AstNode::Boolean(bool, _) => Some(Constant::Bool(*bool)),
+            AstNode::Str(str, _) => Some(Constant::Str(str.clone())),
+            AstNode::Char(char, _) => Some(Constant::Str(char.to_string())),
+            AstNode::InParens(node, _) => self.evaluate_constant(node, constants),
+            AstNode::IfElse {
+                condition,
+                stmt_true,
+                stmt_false,
+                ..
+            } => match self.evaluate_constant(condition, constants)? {
+                Constant::Bool(true) => self.evaluate_constant(stmt_true, constants),
+                Constant::Bool(false) => self.evaluate_constant(stmt_false, constants),
+                _ => None,
+            },
+            AstNode::Identifier { name, args, .. } => {
+                // Names that can't be resolved are reported when the constant is compiled
+                let symbol = self.scope.borrow().resolve(name).ok()?;
 
-        // /
+                if args.is_empty() {
+                    return constants.get(&symbol).cloned();
+                }
 
-        let func_type = self
-            .context
-            .i64_type()
-            .fn_type(&[i64_type.into(), i64_type.into()], false);
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate_constant(arg, constants))
+                    .collect::<Option<Vec<_>>>()?;
+
+                // Overflowing operations are left to be evaluated at runtime
+                match (symbol.as_str(), &args[..]) {
+                    ("+", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        lhs.checked_add(*rhs).map(Constant::Int)
+                    }
+                    ("-", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        lhs.checked_sub(*rhs).map(Constant::Int)
+                    }
+                    ("*", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        lhs.checked_mul(*rhs).map(Constant::Int)
+                    }
+                    ("+%", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs.wrapping_add(*rhs)))
+                    }
+                    ("-%", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs.wrapping_sub(*rhs)))
+                    }
+                    ("*%", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs.wrapping_mul(*rhs)))
+                    }
+                    ("/", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        lhs.checked_div(*rhs).map(Constant::Int)
+                    }
+                    ("%", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        lhs.checked_rem(*rhs).map(Constant::Int)
+                    }
+                    ("&", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs & rhs))
+                    }
+                    ("|", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs | rhs))
+                    }
+                    ("^", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Int(lhs ^ rhs))
+                    }
+                    ("~", [Constant::Int(int)]) => Some(Constant::Int(!int)),
+                    ("<<", [Constant::Int(lhs), Constant::Int(rhs)]) => u32::try_from(*rhs)
+                        .ok()
+                        .and_then(|rhs| lhs.checked_shl(rhs))
+                        .map(Constant::Int),
+                    (">>", [Constant::Int(lhs), Constant::Int(rhs)]) => u32::try_from(*rhs)
+                        .ok()
+                        .and_then(|rhs| lhs.checked_shr(rhs))
+                        .map(Constant::Int),
+                    ("==", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs == rhs))
+                    }
+                    ("!=", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs != rhs))
+                    }
+                    ("<", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs < rhs))
+                    }
+                    (">", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs > rhs))
+                    }
+                    ("<=", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs <= rhs))
+                    }
+                    (">=", [Constant::Int(lhs), Constant::Int(rhs)]) => {
+                        Some(Constant::Bool(lhs >= rhs))
+                    }
+                    ("bool_eq", [Constant::Bool(lhs), Constant::Bool(rhs)]) => {
+                        Some(Constant::Bool(lhs == rhs))
+                    }
+                    ("&&", [Constant::Bool(lhs), Constant::Bool(rhs)]) => {
+                        Some(Constant::Bool(*lhs && *rhs))
+                    }
+                    ("||", [Constant::Bool(lhs), Constant::Bool(rhs)]) => {
+                        Some(Constant::Bool(*lhs || *rhs))
+                    }
+                    ("not", [Constant::Bool(bool)]) => Some(Constant::Bool(!bool)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn constant_value(&self, constant: &Constant) -> BasicValueEnum<'ctx> {
+        match constant {
+            Constant::Int(int) => self.context.i64_type().const_int(*int as u64, true).into(),
+            Constant::Float(float) => self.context.f64_type().const_float(*float).into(),
+            Constant::Bool(bool) => self
+                .context
+                .bool_type()
+                .const_int(*bool as u64, false)
+                .into(),
+            Constant::Str(str) => self.build_string(str).into(),
+        }
+    }
 
-        let func = self.core.add_function("/", func_type, None);
+    /// Checks whether a function may call external functions like ```printf```
+    fn has_side_effects(&self, symbol: &str) -> bool {
+        let call_graph = self.call_graph.borrow();
+        let mut visited = HashSet::new();
+        let mut stack = vec![symbol.to_string()];
 
-        self.builder
-            .position_at_end(self.context.append_basic_block(func, "entry"));
+        while let Some(function) = stack.pop() {
+            if !visited.insert(function.clone()) {
+                continue;
+            }
+
+            match call_graph.get(&function) {
+                Some(called) => stack.extend(called.iter().cloned()),
+                None => {
+                    let is_external = self
+                        .get_function(&function)
+                        .map(|function| function.count_basic_blocks() == 0)
+                        .unwrap_or(false);
+
+                    if is_external {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
 
-        let lhs = func.get_nth_param(0).unwrap().into_int_value();
-        let rhs = func.get_nth_param(1).unwrap().into_int_value();
+    /// Compiles the body of a constant that couldn't be evaluated at compile time
+    ///
+    /// The constant is evaluated the first time it's used and cached afterwards
+    fn compile_lazy_constant(
+        &self,
+        function: FunctionValue<'ctx>,
+        value: AstNode,
+    ) -> Result<(), FireworkError> {
+        let name = function.get_name().to_string_lossy().to_string();
+        let value_type = function.get_type().get_return_type().unwrap();
+        let bool_type = self.context.bool_type();
 
+        let cache = self
+            .module
+            .add_global(value_type, None, &format!("{}.value", name));
+        cache.set_initializer(&value_type.const_zero());
+        cache.set_linkage(Linkage::Internal);
+
+        let initialized = self
+            .module
+            .add_global(bool_type, None, &format!("{}.initialized", name));
+        initialized.set_initializer(&bool_type.const_zero());
+        initialized.set_linkage(Linkage::Internal);
+
+        let init_bb = self.context.append_basic_block(function, "init");
+        let cached_bb = self.context.append_basic_block(function, "cached");
+
+        let is_initialized = self
+            .builder
+            .build_load(initialized.as_pointer_value(), "is_initialized")
+            .into_int_value();
         self.builder
-            .build_return(Some(&self.builder.build_int_signed_div(lhs, rhs, "div")));
+            .build_conditional_branch(is_initialized, cached_bb, init_bb);
+
+        self.builder.position_at_end(init_bb);
+        let value = any_value_enum_to_basic_value_enum!(
+            self.with_expected_type(Some(value_type), || self.compile_astnode(value))?
+        );
+        self.builder.build_store(cache.as_pointer_value(), value);
+        self.builder.build_store(
+            initialized.as_pointer_value(),
+            bool_type.const_int(1, false),
+        );
+        self.builder.build_return(Some(&value));
+
+        self.builder.position_at_end(cached_bb);
+        let value = self.builder.build_load(cache.as_pointer_value(), "cached");
+        self.builder.build_return(Some(&value));
+
+        Ok(())
+    }
+
+    fn add_default_functions(&self) -> Result<(), FireworkError> {
+        let i64_type = self.context.i64_type();
+        let bool_type = self.context.bool_type();
 
-        let core = if is_core_installed() {
-            fs::read_to_string(get_core_path())
+        if self.is_wasm_target() {
+            self.add_wasi_runtime()?;
         } else {
-            install_core().unwrap();
-            fs::read_to_string(get_core_path())
+            self.add_libc_functions();
+        }
+
+        // Comparisons, calls to them are compiled at the call site
+        for (name, predicate, _) in COMPARISONS {
+            self.add_core_function(name, &[i64_type, i64_type], bool_type, |params| {
+                self.builder
+                    .build_int_compare(predicate, params[0], params[1], "cmp")
+            });
         }
-        .unwrap();
 
-        let parsed = parse(&core).unwrap();
+        self.add_core_function("bool_eq", &[bool_type, bool_type], bool_type, |params| {
+            self.builder
+                .build_int_compare(IntPredicate::EQ, params[0], params[1], "eq")
+        });
+
+        // Arithmetic
+        self.add_core_function("+", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_add(params[0], params[1], "sum")
+        });
+
+        self.add_core_function("-", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_sub(params[0], params[1], "sub")
+        });
+
+        self.add_core_function("*", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_mul(params[0], params[1], "mul")
+        });
+
+        // Wrapping arithmetic, never checked for overflow
+        self.add_core_function("+%", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_add(params[0], params[1], "sum")
+        });
+
+        self.add_core_function("-%", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_sub(params[0], params[1], "sub")
+        });
+
+        self.add_core_function("*%", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_int_mul(params[0], params[1], "mul")
+        });
+
+        self.add_core_function("/", &[i64_type, i64_type], i64_type, |params| {
+            self.builder
+                .build_int_signed_div(params[0], params[1], "div")
+        });
+
+        self.add_core_function("%", &[i64_type, i64_type], i64_type, |params| {
+            self.builder
+                .build_int_signed_rem(params[0], params[1], "rem")
+        });
+
+        // Bitwise operators
+        self.add_core_function("&", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_and(params[0], params[1], "and")
+        });
+
+        self.add_core_function("|", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_or(params[0], params[1], "or")
+        });
+
+        self.add_core_function("^", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_xor(params[0], params[1], "xor")
+        });
+
+        self.add_core_function("~", &[i64_type], i64_type, |params| {
+            self.builder.build_not(params[0], "complement")
+        });
+
+        self.add_core_function("<<", &[i64_type, i64_type], i64_type, |params| {
+            self.builder.build_left_shift(params[0], params[1], "shl")
+        });
+
+        self.add_core_function(">>", &[i64_type, i64_type], i64_type, |params| {
+            self.builder
+                .build_right_shift(params[0], params[1], true, "shr")
+        });
 
-        parsed.iter().cloned().for_each(|n| {
-            self.compile_astnode(n).unwrap();
+        // Boolean operators, && and || are compiled at the call site so that they short-circuit
+        self.add_core_function("not", &[bool_type], bool_type, |params| {
+            self.builder.build_not(params[0], "not")
         });
 
-        self.module.link_in_module(self.core.clone()).unwrap();
+        self.add_panic_function();
+
+        let (core, core_path) = match &self.core_source {
+            Some(source) => (source.clone(), PathBuf::from("core.firework")),
+            None => {
+                if !is_core_installed() {
+                    install_core()?;
+                }
+
+                let core_path = get_core_path()?;
+                let core = fs::read_to_string(&core_path)
+                    .map_err(|err| FireworkError::io(&core_path, err))?;
+
+                (core, core_path)
+            }
+        };
+
+        // The core's spans don't point into the project, so its errors are reported as a whole
+        let invalid_core = |err: FireworkError| {
+            FireworkError::CoreInstall(format!(
+                "The core library at {} is invalid: {}",
+                core_path.display(),
+                err
+            ))
+        };
+
+        let parsed = parse(&core).map_err(invalid_core)?;
+
+        *self.source_path.borrow_mut() = core_path.display().to_string();
+
+        self.compile_ast(&parsed).map_err(invalid_core)?;
+
+        self.module
+            .link_in_module(self.core.clone())
+            .map_err(|err| FireworkError::Codegen(err.to_string()))
     }
 
-    fn compile_type(&self, node_type: AstNode) -> BasicMetadataTypeEnum<'ctx> {
-        match node_type {
-            AstNode::Type(typ) => match &typ[..] {
-                "i64" => self.context.i64_type().into(),
+    /// Declares the libc functions every program can call
+    fn add_libc_functions(&self) {
+        let i64_type = self.context.i64_type();
+
+        // Puts
+        let func_type = i64_type.fn_type(
+            &[self
+                .context
+                .i8_type()
+                .ptr_type(AddressSpace::Generic)
+                .into()],
+            false,
+        );
+
+        self.module
+            .add_function("puts", func_type, Some(Linkage::External));
+
+        // Printf
+        let func_type = i64_type.fn_type(
+            &[self
+                .context
+                .i8_type()
+                .ptr_type(AddressSpace::Generic)
+                .into()],
+            true,
+        );
+
+        self.module
+            .add_function("printf", func_type, Some(Linkage::External));
+    }
+
+    /// Adds the runtime implementing ```puts```, ```printf```, ```dprintf``` and ```exit``` on top
+    /// of WASI, WebAssembly modules don't link with libc
+    fn add_wasi_runtime(&self) -> Result<(), FireworkError> {
+        let buffer = MemoryBuffer::create_from_memory_range_copy(WASI_RUNTIME.as_bytes(), "wasi");
+        let runtime = self.context.create_module_from_ir(buffer).map_err(|err| {
+            FireworkError::Codegen(format!("The WASI runtime is invalid: {}", err))
+        })?;
+
+        runtime.set_triple(&self.module.get_triple());
+        runtime.set_data_layout(&self.module.get_data_layout());
+
+        let mut libc_functions = vec![];
+        let mut function = runtime.get_first_function();
+
+        while let Some(current) = function {
+            if current.count_basic_blocks() > 0 && current.get_linkage() == Linkage::External {
+                libc_functions.push(current.get_name().to_string_lossy().to_string());
+            }
+
+            function = current.get_next_function();
+        }
+
+        self.module.link_in_module(runtime).map_err(|err| {
+            FireworkError::Codegen(format!("Couldn't link the WASI runtime: {}", err))
+        })?;
+
+        // They're only called by the program, they mustn't clash with the symbols of libraries
+        for name in libc_functions {
+            self.get_function(&name)
+                .unwrap()
+                .set_linkage(Linkage::Internal);
+        }
+
+        Ok(())
+    }
+
+    /// Adds ```_start```, the entry point of WebAssembly modules, which exits with the status
+    /// returned by main
+    fn add_wasm_entry_point(&self, main: FunctionValue<'ctx>) {
+        let i32_type = self.context.i32_type();
+        let exit = self.get_function("exit").unwrap();
+
+        let start =
+            self.module
+                .add_function("_start", self.context.void_type().fn_type(&[], false), None);
+        start.add_attribute(
+            AttributeLoc::Function,
+            self.context
+                .create_string_attribute("wasm-export-name", "_start"),
+        );
+
+        self.builder
+            .position_at_end(self.context.append_basic_block(start, "entry"));
+
+        let status = self
+            .builder
+            .build_call(main, &[], "status")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        let status = self
+            .builder
+            .build_int_cast(status.into_int_value(), i32_type, "status");
+
+        self.builder.build_call(exit, &[status.into()], "");
+        self.builder.build_unreachable();
+    }
+
+    /// Adds the routine called by panicking programs, it prints
+    /// ```panicked at file:line:column: message``` to stderr and exits with a non-zero status
+    fn add_panic_function(&self) {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let void_type = self.context.void_type();
+
+        let noreturn = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("noreturn"), 0);
+        let cold = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("cold"), 0);
+
+        // WebAssembly modules already define them in the WASI runtime
+        let dprintf = self.get_function("dprintf").unwrap_or_else(|| {
+            self.module.add_function(
+                "dprintf",
+                i32_type.fn_type(&[i32_type.into(), i8_ptr_type.into()], true),
+                Some(Linkage::External),
+            )
+        });
+        let exit = self.get_function("exit").unwrap_or_else(|| {
+            self.module.add_function(
+                "exit",
+                void_type.fn_type(&[i32_type.into()], false),
+                Some(Linkage::External),
+            )
+        });
+        exit.add_attribute(AttributeLoc::Function, noreturn);
+
+        let panic_function = self.module.add_function(
+            PANIC_FUNCTION,
+            void_type.fn_type(
+                &[
+                    i8_ptr_type.into(),
+                    i8_ptr_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+            Some(Linkage::Internal),
+        );
+        panic_function.add_attribute(AttributeLoc::Function, noreturn);
+        panic_function.add_attribute(AttributeLoc::Function, cold);
+
+        self.builder
+            .position_at_end(self.context.append_basic_block(panic_function, "entry"));
+
+        let params = panic_function.get_params();
+        let stderr = i32_type.const_int(2, false);
+        let format = self.build_string("panicked at %s:%lld:%lld: %s\n");
+
+        self.builder.build_call(
+            dprintf,
+            &[
+                stderr.into(),
+                format.into(),
+                params[1].into(),
+                params[2].into(),
+                params[3].into(),
+                params[0].into(),
+            ],
+            "",
+        );
+        self.builder.build_call(
+            exit,
+            &[i32_type.const_int(PANIC_EXIT_CODE, false).into()],
+            "",
+        );
+        self.builder.build_unreachable();
+    }
+
+    fn compile_type(
+        &self,
+        node_type: AstNode,
+    ) -> Result<BasicMetadataTypeEnum<'ctx>, FireworkError> {
+        Ok(self.compile_basic_type(node_type)?.into())
+    }
+
+    fn compile_basic_type(&self, node_type: AstNode) -> Result<BasicTypeEnum<'ctx>, FireworkError> {
+        Ok(match node_type {
+            AstNode::Type(typ, span) => match &typ[..] {
+                "i64" | "u64" => self.context.i64_type().into(),
+                "i32" | "u32" => self.context.i32_type().into(),
+                "i16" | "u16" => self.context.i16_type().into(),
+                "i8" | "u8" => self.context.i8_type().into(),
+                "f64" => self.context.f64_type().into(),
                 "bool" => self.context.bool_type().into(),
                 "str" => self
                     .context
                     .i8_type()
                     .ptr_type(AddressSpace::Generic)
                     .into(),
+                "Unit" => self.unit_type().into(),
                 "char" => self
                     .context
                     .i8_type()
                     .ptr_type(AddressSpace::Generic)
                     .into(),
-                _ => panic!(),
+                _ => return Err(type_error(format!("Unknown type `{}`", typ), span)),
             },
-            _ => panic!(),
+            AstNode::PointerType(pointee, _) => self
+                .compile_basic_type(*pointee)?
+                .ptr_type(AddressSpace::Generic)
+                .into(),
+            AstNode::GenericType { name, args, span } => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| self.compile_basic_type(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match (&name[..], args.len()) {
+                    ("Option", 1) => self.sum_type(SumType::Option, &args).into(),
+                    ("Result", 2) => self.sum_type(SumType::Result, &args).into(),
+                    _ => {
+                        return Err(type_error(
+                            format!("Type `{}` doesn't take {} type arguments", name, args.len()),
+                            span,
+                        ))
+                    }
+                }
+            }
+            node => return Err(type_error("Expected a type", node.span())),
+        })
+    }
+
+    /// Returns the C type a Firework type is passed as by exported functions, following
+    /// ```compile_type```, or ```None``` if the type has no C equivalent
+    pub fn c_type(&self, node_type: &AstNode) -> Option<String> {
+        match node_type {
+            AstNode::Type(typ, _) => Some(
+                match &typ[..] {
+                    "i64" => "int64_t",
+                    "u64" => "uint64_t",
+                    "i32" => "int32_t",
+                    "u32" => "uint32_t",
+                    "i16" => "int16_t",
+                    "u16" => "uint16_t",
+                    "i8" => "int8_t",
+                    "u8" => "uint8_t",
+                    "f64" => "double",
+                    "bool" => "bool",
+                    "str" | "char" => "char *",
+                    "Unit" => "void",
+                    _ => return None,
+                }
+                .to_string(),
+            ),
+            AstNode::PointerType(pointee, _) => {
+                let pointee = self.c_type(pointee)?;
+
+                if pointee.ends_with('*') {
+                    Some(format!("{}*", pointee))
+                } else {
+                    Some(format!("{} *", pointee))
+                }
+            }
+            _ => None,
         }
     }
 
-    pub fn compile(&self, ast: AST) {
-        self.add_default_functions();
-        ast.iter().cloned().for_each(|n| {
-            self.compile_astnode(n).unwrap();
-        });
+    /// Generates a C header declaring the exported functions of the given modules
+    pub fn c_header(&self, modules: &[SourceModule], guard: &str) -> String {
+        let prototypes = modules
+            .iter()
+            .flat_map(|module| &module.ast)
+            .filter_map(|node| match node {
+                AstNode::Fn {
+                    name,
+                    return_type,
+                    args,
+                    exported: true,
+                    ..
+                } => {
+                    let params = match &**args {
+                        AstNode::FnArgs(args, _) if args.is_empty() => "void".to_string(),
+                        AstNode::FnArgs(args, _) => args
+                            .iter()
+                            .map(|(param, param_type)| {
+                                let param = match param {
+                                    AstNode::Identifier { name, .. } => name,
+                                    _ => unreachable!(),
+                                };
+
+                                c_declaration(&self.c_type(param_type).unwrap(), param)
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        _ => unreachable!(),
+                    };
+
+                    Some(format!(
+                        "{}({});\n",
+                        c_declaration(&self.c_type(return_type).unwrap(), name),
+                        params
+                    ))
+                }
+                _ => None,
+            })
+            .collect::<String>();
+
+        format!(
+            "/* Generated by firework_lang, do not edit */\n\
+             #ifndef {guard}\n\
+             #define {guard}\n\
+             \n\
+             #include <stdbool.h>\n\
+             #include <stdint.h>\n\
+             \n\
+             #ifdef __cplusplus\n\
+             extern \"C\" {{\n\
+             #endif\n\
+             \n\
+             {prototypes}\
+             \n\
+             #ifdef __cplusplus\n\
+             }}\n\
+             #endif\n\
+             \n\
+             #endif\n",
+            guard = guard,
+            prototypes = prototypes
+        )
+    }
+
+    /// Compiles the given modules in order, so a module has to come after the modules it imports
+    pub fn compile(&self, modules: Vec<SourceModule>) -> Result<(), FireworkError> {
+        self.add_default_functions()?;
+
+        // The main module comes last
+        if let Some(main_module) = modules.last().filter(|_| self.emit_debug_info) {
+            *self.debug_info.borrow_mut() = Some(DebugInfo::new(
+                self.context,
+                &self.module,
+                &main_module.path,
+                self.opt_level != OptLevel::O0,
+            ));
+        }
+
+        for module in &modules {
+            *self.scope.borrow_mut() = ModuleScope::new(module, &modules)?;
+            *self.source_path.borrow_mut() = module.path.display().to_string();
+
+            if let Some(debug_info) = &*self.debug_info.borrow() {
+                debug_info.set_file(&module.path);
+            }
+
+            self.compile_ast(&module.ast)?;
+        }
+
+        if let Some(main) = self.get_function("main").filter(|_| self.is_wasm_target()) {
+            self.add_wasm_entry_point(main);
+        }
+
+        if let Some(debug_info) = &*self.debug_info.borrow() {
+            debug_info.finalize();
+        }
+
+        // LLVM aborts on invalid IR the checks above missed instead of reporting it
+        self.module.verify().map_err(|err| {
+            FireworkError::Codegen(format!("LLVM IR of the program is invalid: {}", err))
+        })
     }
 
     /// # Safety
     ///
     /// Should be called to run the main function after the program has been compiled
-    pub(crate) unsafe fn call_main(&self) {
+    ///
+    /// Returns the program's exit code
+    pub(crate) unsafe fn call_main(&self) -> i32 {
         self.execution_engine
-            .run_function_as_main(self.get_function("main").unwrap(), &[]);
+            .run_function_as_main(self.get_function("main").unwrap(), &[])
+    }
+
+    /// Runs the optimization pipeline of the optimization level on the compiled code
+    ///
+    /// Function passes run on each function first, then module passes, e.g. the inliner, run on
+    /// the whole module
+    pub fn optimize(&self) {
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(self.opt_level.into());
+
+        match self.opt_level {
+            OptLevel::O0 | OptLevel::O1 => (),
+            OptLevel::O2 => builder.set_inliner_with_threshold(225),
+            OptLevel::O3 => builder.set_inliner_with_threshold(250),
+            OptLevel::Os => {
+                builder.set_size_level(1);
+                builder.set_inliner_with_threshold(75);
+            }
+        }
+
+        let function_passes = PassManager::create(&self.module);
+
+        // Turns tail recursion into loops before the returns of functions get merged, which
+        // keeps the calls from being emitted as tail calls on targets without tailcc
+        if self.opt_level != OptLevel::O0 {
+            function_passes.add_tail_call_elimination_pass();
+        }
+
+        builder.populate_function_pass_manager(&function_passes);
+
+        function_passes.initialize();
+
+        let mut function = self.module.get_first_function();
+
+        while let Some(current) = function {
+            function_passes.run_on(&current);
+            function = current.get_next_function();
+        }
+
+        function_passes.finalize();
+
+        let module_passes = PassManager::create(());
+        builder.populate_module_pass_manager(&module_passes);
+        module_passes.run_on(&self.module);
+
+        if let Some(stream) = self.print_after_passes {
+            let ir = format!(
+                "; IR after the -O{} passes\n{}",
+                format!("{:?}", self.opt_level).trim_start_matches('O'),
+                self.ir()
+            );
+
+            match stream {
+                IrStream::Stdout => println!("{}", ir),
+                IrStream::Stderr => eprintln!("{}", ir),
+            }
+        }
+    }
+
+    /// Returns the compiled code as LLVM IR
+    pub fn ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
+
+    /// Returns the compiled code as LLVM bitcode
+    pub fn bitcode(&self) -> Vec<u8> {
+        self.module.write_bitcode_to_memory().as_slice().to_vec()
+    }
+
+    /// Returns the compiled code as assembly for the target
+    pub fn assembly(&self) -> Result<Vec<u8>, FireworkError> {
+        self.machine_code(FileType::Assembly)
+    }
+
+    /// Returns the compiled code as an object file for the target
+    pub fn object(&self) -> Result<Vec<u8>, FireworkError> {
+        self.machine_code(FileType::Object)
+    }
+
+    fn machine_code(&self, file_type: FileType) -> Result<Vec<u8>, FireworkError> {
+        let buffer = self
+            .target_machine(RelocMode::PIC)?
+            .write_to_memory_buffer(&self.module, file_type)
+            .map_err(|err| FireworkError::Codegen(err.to_string()))?;
+
+        Ok(buffer.as_slice().to_vec())
     }
 
-    pub fn dump_ir(&self) -> Result<(), LLVMString> {
-        self.module.print_to_file("ir.ll")
+    /// Gives every definition internal linkage except exported functions, so that a library
+    /// doesn't leak its internal symbols into the programs linking it
+    pub fn hide_internal_symbols(&self) {
+        let exported = self.exported.borrow();
+        let mut function = self.module.get_first_function();
+
+        while let Some(current) = function {
+            if current.count_basic_blocks() > 0
+                && !exported.contains(current.get_name().to_str().unwrap())
+            {
+                current.set_linkage(Linkage::Internal);
+            }
+
+            function = current.get_next_function();
+        }
+
+        let mut global = self.module.get_first_global();
+
+        while let Some(current) = global {
+            if !current.is_declaration() && current.get_linkage() == Linkage::External {
+                current.set_linkage(Linkage::Internal);
+            }
+
+            global = current.get_next_global();
+        }
     }
 
-    pub fn dump_asm(&self) -> Result<(), LLVMString> {
-        Target::initialize_native(&InitializationConfig::default())
-            .expect("Failed to initialize native target");
+    /// Creates a machine generating code for the target, the host unless another one was set
+    fn target_machine(&self, reloc_mode: RelocMode) -> Result<TargetMachine, FireworkError> {
+        let TargetOptions {
+            triple,
+            cpu,
+            features,
+        } = &self.target;
+
+        // Code for the host is tuned for its CPU, cross-compiled code for a generic one
+        let (triple, default_cpu, default_features) = match triple {
+            Some(triple) => {
+                initialize_target(triple);
+                (
+                    TargetTriple::create(triple),
+                    generic_cpu(triple).to_string(),
+                    default_features(triple).to_string(),
+                )
+            }
+            None => {
+                Target::initialize_native(&InitializationConfig::default())
+                    .map_err(FireworkError::Codegen)?;
+                (
+                    TargetMachine::get_default_triple(),
+                    TargetMachine::get_host_cpu_name().to_string(),
+                    TargetMachine::get_host_cpu_features().to_string(),
+                )
+            }
+        };
+
+        // WebAssembly's fast instruction selector, used without optimizations, ignores tail
+        // calls, so recursive functions would overflow the stack
+        let opt_level = match self.opt_level {
+            OptLevel::O0 if self.is_wasm_target() => OptLevel::O1,
+            opt_level => opt_level,
+        };
 
-        let triple = TargetMachine::get_default_triple();
-        let cpu = TargetMachine::get_host_cpu_name().to_string();
-        let features = TargetMachine::get_host_cpu_features().to_string();
+        let name = triple.as_str().to_string_lossy();
+        let target = Target::from_triple(&triple).map_err(|err| {
+            FireworkError::Codegen(format!("Unsupported target `{}`: {}", name, err))
+        })?;
 
-        let target = Target::from_triple(&triple).unwrap();
-        let machine = target
+        target
             .create_target_machine(
                 &triple,
-                &cpu,
-                &features,
-                OptimizationLevel::Aggressive,
-                RelocMode::Default,
+                cpu.as_deref().unwrap_or(&default_cpu),
+                features.as_deref().unwrap_or(&default_features),
+                opt_level.into(),
+                reloc_mode,
                 CodeModel::Default,
             )
-            .unwrap();
+            .ok_or_else(|| {
+                FireworkError::Codegen(format!("Couldn't create a target machine for `{}`", name))
+            })
+    }
+}
+
+/// Returns the name LLVM gives to the baseline CPU of a target triple's architecture
+fn generic_cpu(triple: &str) -> &'static str {
+    match triple.split('-').next().unwrap_or_default() {
+        "riscv32" => "generic-rv32",
+        "riscv64" => "generic-rv64",
+        _ => "generic",
+    }
+}
+
+/// Returns the CPU features enabled unless others are given
+fn default_features(triple: &str) -> &'static str {
+    // WebAssembly can only tail call optimize with the tail call proposal, without it tail
+    // recursive functions overflow the stack
+    if triple.starts_with("wasm") {
+        "+tail-call"
+    } else {
+        ""
+    }
+}
 
-        machine.write_to_file(&self.module, FileType::Assembly, "out.asm".as_ref())?;
+/// Initializes LLVM's code generator for the architecture of a target triple
+fn initialize_target(triple: &str) {
+    let config = InitializationConfig::default();
+    let arch = triple.split('-').next().unwrap_or_default();
 
-        Ok(())
+    match arch {
+        "x86_64" | "i386" | "i586" | "i686" => Target::initialize_x86(&config),
+        "aarch64" | "aarch64_be" | "arm64" => Target::initialize_aarch64(&config),
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => {
+            Target::initialize_arm(&config)
+        }
+        _ if arch.starts_with("wasm") => Target::initialize_webassembly(&config),
+        // inkwell's RISC-V initializer skips the assembly printer, which emits object files too
+        _ => Target::initialize_all(&config),
+    }
+}
+
+/// Declares a C variable or function, e.g. ```char *name```
+fn c_declaration(c_type: &str, name: &str) -> String {
+    if c_type.ends_with('*') {
+        format!("{}{}", c_type, name)
+    } else {
+        format!("{} {}", c_type, name)
+    }
+}
+
+/// Checks whether a type annotation refers to the `Unit` type
+fn is_unit_type(node_type: &AstNode) -> bool {
+    matches!(node_type, AstNode::Type(typ, _) if typ == "Unit")
+}
+
+/// Returns the signed and unsigned predicates of a comparison
+fn comparison_predicates(symbol: &str) -> Option<(IntPredicate, IntPredicate)> {
+    COMPARISONS
+        .iter()
+        .find(|(name, ..)| *name == symbol)
+        .map(|&(_, signed, unsigned)| (signed, unsigned))
+}
+
+/// Checks whether an integer fits in an integer type of the given width, either as a signed
+/// or as an unsigned integer
+fn fits_in(int: i64, width: u32) -> bool {
+    width >= 64 || (-(1 << (width - 1))..1 << width).contains(&int)
+}
+
+/// Checks whether a type annotation refers to an unsigned integer type
+fn is_unsigned_type(node_type: &AstNode) -> bool {
+    matches!(node_type, AstNode::Type(typ, _) if ["u8", "u16", "u32", "u64"].contains(&&typ[..]))
+}
+
+/// Returns the names bound with ```let``` anywhere inside an expression
+fn let_bindings(node: &AstNode) -> Vec<String> {
+    match node {
+        AstNode::LetBinding { name, value, .. } => {
+            let mut names = let_bindings(value);
+            names.push(name.clone());
+            names
+        }
+        AstNode::Do(stmts, _) => stmts.iter().flat_map(let_bindings).collect::<Vec<_>>(),
+        AstNode::IfElse {
+            condition,
+            stmt_true,
+            stmt_false,
+            ..
+        } => [condition, stmt_true, stmt_false]
+            .iter()
+            .flat_map(|node| let_bindings(node))
+            .collect::<Vec<_>>(),
+        AstNode::InParens(node, _) | AstNode::Try(node, _) => let_bindings(node),
+        AstNode::Match { value, arms, .. } => {
+            let mut names = let_bindings(value);
+
+            for (pattern, expr) in arms {
+                if let AstNode::Pattern {
+                    constructor,
+                    binding,
+                    ..
+                } = pattern
+                {
+                    names.extend(binding.iter().cloned());
+
+                    if !CONSTRUCTORS.contains(&constructor.as_str()) {
+                        names.push(constructor.clone());
+                    }
+                }
+                names.extend(let_bindings(expr));
+            }
+
+            names
+        }
+        AstNode::Identifier { args, .. } => args.iter().flat_map(let_bindings).collect::<Vec<_>>(),
+        _ => vec![],
     }
 }
+
+/// Returns an error about the code at ```span```
+fn type_error(message: impl Into<String>, span: Span) -> FireworkError {
+    Diagnostic::error(message).with_span(span).into()
+}
+
+/// Returns an error about a feature the compiler doesn't support yet
+fn not_implemented(feature: String) -> FireworkError {
+    FireworkError::Codegen(format!("{} is not implemented yet", feature))
+}
+
+/// Returns the operands of a binary operator or builtin
+fn two_args(name: &str, args: Vec<AstNode>, span: Span) -> Result<[AstNode; 2], FireworkError> {
+    <[AstNode; 2]>::try_from(args)
+        .map_err(|_| type_error(format!("`{}` takes exactly 2 arguments", name), span))
+}
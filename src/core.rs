@@ -1,22 +1,29 @@
+use crate::error::FireworkError;
 use home::home_dir;
-use std::error::Error;
 use std::fs::{self};
 use std::path::PathBuf;
 
-pub fn get_core_path() -> PathBuf {
-    let home = home_dir().unwrap_or_else(|| panic!("Could access your home directory"));
-    home.join(".firework_lang").join("core.firework")
+pub fn get_core_path() -> Result<PathBuf, FireworkError> {
+    let home = home_dir().ok_or_else(|| {
+        FireworkError::CoreInstall("Couldn't access your home directory".to_string())
+    })?;
+    Ok(home.join(".firework_lang").join("core.firework"))
 }
 
 pub fn is_core_installed() -> bool {
-    get_core_path().exists()
+    matches!(get_core_path(), Ok(core_path) if core_path.exists())
 }
 
-pub fn install_core() -> Result<(), Box<dyn Error>> {
-    let core = reqwest::blocking::get("https://firework-lang.netlify.app/core.firework")?.text()?;
-    let core_path = get_core_path();
+pub fn install_core() -> Result<(), FireworkError> {
+    let core = reqwest::blocking::get("https://firework-lang.netlify.app/core.firework")
+        .and_then(|response| response.text())
+        .map_err(|err| {
+            FireworkError::CoreInstall(format!("Couldn't download the core library: {}", err))
+        })?;
+    let core_path = get_core_path()?;
+    let core_dir = core_path.parent().unwrap();
 
-    fs::create_dir_all(core_path.parent().unwrap())?;
-    fs::write(core_path, core)?;
+    fs::create_dir_all(core_dir).map_err(|err| FireworkError::io(core_dir, err))?;
+    fs::write(&core_path, core).map_err(|err| FireworkError::io(&core_path, err))?;
     Ok(())
 }
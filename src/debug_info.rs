@@ -0,0 +1,255 @@
+//! DWARF debug information, so debuggers can step through the Firework source
+extern crate inkwell_llvm12 as inkwell;
+
+use std::cell::Cell;
+use std::path::Path;
+
+use crate::parser::Location;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+    DISubprogram, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    LLVMDWARFTypeEncoding,
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::targets::TargetData;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{FunctionValue, PointerValue};
+
+/// Version of DWARF that is emitted, the newest one every debugger reads
+const DWARF_VERSION: u64 = 4;
+
+const DW_ATE_ADDRESS: LLVMDWARFTypeEncoding = 0x01;
+const DW_ATE_BOOLEAN: LLVMDWARFTypeEncoding = 0x02;
+const DW_ATE_FLOAT: LLVMDWARFTypeEncoding = 0x04;
+const DW_ATE_SIGNED: LLVMDWARFTypeEncoding = 0x05;
+const DW_ATE_UNSIGNED: LLVMDWARFTypeEncoding = 0x07;
+
+/// Debug information of a module, with the compile unit and the subprogram of the function
+/// being compiled
+pub struct DebugInfo<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    /// Sizes of the types on the target
+    target_data: TargetData,
+    /// Source file being compiled
+    file: Cell<DIFile<'ctx>>,
+    /// Function being compiled, instructions get locations inside of it
+    subprogram: Cell<Option<DISubprogram<'ctx>>>,
+    optimized: bool,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    /// Creates the compile unit of a program whose main source file is ```path```
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        path: &Path,
+        optimized: bool,
+    ) -> Self {
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(DWARF_VERSION, false),
+        );
+
+        let (file_name, directory) = split_path(path);
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            // Debuggers don't know Firework, C is the closest language they can display
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            &format!("firework_lang {}", env!("CARGO_PKG_VERSION")),
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = compile_unit.get_file();
+
+        DebugInfo {
+            context,
+            builder,
+            compile_unit,
+            target_data: TargetData::create(module.get_data_layout().as_str().to_str().unwrap()),
+            file: Cell::new(file),
+            subprogram: Cell::new(None),
+            optimized,
+        }
+    }
+
+    /// Sets the source file the following functions are defined in
+    pub fn set_file(&self, path: &Path) {
+        let (file_name, directory) = split_path(path);
+
+        self.file
+            .set(self.builder.create_file(&file_name, &directory));
+    }
+
+    /// Returns the debug type of a value of type ```value_type```, named ```name``` in the
+    /// source
+    pub fn debug_type(&self, name: &str, value_type: BasicTypeEnum<'ctx>) -> DIType<'ctx> {
+        let size = self.target_data.get_bit_size(&value_type);
+
+        let encoding = match value_type {
+            BasicTypeEnum::IntType(int) if int.get_bit_width() == 1 => DW_ATE_BOOLEAN,
+            BasicTypeEnum::IntType(_) if name.starts_with('u') => DW_ATE_UNSIGNED,
+            BasicTypeEnum::IntType(_) => DW_ATE_SIGNED,
+            BasicTypeEnum::FloatType(_) => DW_ATE_FLOAT,
+            // inkwell can't create pointer types, so pointers are shown as plain addresses
+            BasicTypeEnum::PointerType(_) => DW_ATE_ADDRESS,
+            _ => {
+                return self
+                    .builder
+                    .create_struct_type(
+                        self.compile_unit.as_debug_info_scope(),
+                        name,
+                        self.file.get(),
+                        0,
+                        size,
+                        self.target_data.get_abi_alignment(&value_type) * 8,
+                        DIFlags::ZERO,
+                        None,
+                        &[],
+                        0,
+                        None,
+                        name,
+                    )
+                    .as_type()
+            }
+        };
+
+        // Booleans take a whole byte in memory
+        let size = if encoding == DW_ATE_BOOLEAN { 8 } else { size };
+
+        self.builder
+            .create_basic_type(name, size, encoding, DIFlags::ZERO)
+            .unwrap()
+            .as_type()
+    }
+
+    /// Attaches a subprogram to ```function```, the following instructions are located inside
+    /// of it until ```end_function``` is called
+    pub fn begin_function(
+        &self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        location: &Location,
+        return_type: Option<DIType<'ctx>>,
+        param_types: &[DIType<'ctx>],
+    ) {
+        let file = self.file.get();
+        let subroutine_type =
+            self.builder
+                .create_subroutine_type(file, return_type, param_types, DIFlags::ZERO);
+        let linkage_name = function.get_name().to_str().unwrap();
+
+        let subprogram = self.builder.create_function(
+            file.as_debug_info_scope(),
+            name,
+            Some(linkage_name).filter(|&symbol| symbol != name),
+            file,
+            location.line as u32,
+            subroutine_type,
+            false,
+            true,
+            location.line as u32,
+            DIFlags::PROTOTYPED,
+            self.optimized,
+        );
+
+        function.set_subprogram(subprogram);
+        self.subprogram.set(Some(subprogram));
+    }
+
+    /// Stops locating instructions inside the function being compiled
+    pub fn end_function(&self, builder: &Builder<'ctx>) {
+        self.subprogram.set(None);
+        builder.unset_current_debug_location();
+    }
+
+    /// Sets the source location of the instructions built next
+    pub fn set_location(&self, builder: &Builder<'ctx>, location: &Location) {
+        if let Some(subprogram) = self.subprogram.get() {
+            let location = self.builder.create_debug_location(
+                self.context,
+                location.line as u32,
+                location.column as u32,
+                subprogram.as_debug_info_scope(),
+                None,
+            );
+
+            builder.set_current_debug_location(self.context, location);
+        }
+    }
+
+    /// Describes the parameter number ```arg_no``` of the function being compiled, whose
+    /// value is stored at ```storage```
+    pub fn declare_parameter(
+        &self,
+        builder: &Builder<'ctx>,
+        storage: PointerValue<'ctx>,
+        name: &str,
+        arg_no: u32,
+        location: &Location,
+        param_type: DIType<'ctx>,
+    ) {
+        let subprogram = self.subprogram.get().unwrap();
+        let variable = self.builder.create_parameter_variable(
+            subprogram.as_debug_info_scope(),
+            name,
+            arg_no,
+            self.file.get(),
+            location.line as u32,
+            param_type,
+            true,
+            DIFlags::ZERO,
+        );
+        let debug_location = self.builder.create_debug_location(
+            self.context,
+            location.line as u32,
+            location.column as u32,
+            subprogram.as_debug_info_scope(),
+            None,
+        );
+
+        self.builder.insert_declare_at_end(
+            storage,
+            Some(variable),
+            None,
+            debug_location,
+            builder.get_insert_block().unwrap(),
+        );
+    }
+
+    /// Resolves the debug information, has to be called before the module is emitted
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
+
+/// Splits a source path into the path relative to the working directory, which is the
+/// project's root, and the working directory
+fn split_path(path: &Path) -> (String, String) {
+    let directory = std::env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+
+    (path.display().to_string(), directory)
+}
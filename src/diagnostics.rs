@@ -0,0 +1,501 @@
+//! Errors and warnings pointing at the source code they're about
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::parser::{FileId, Rule, Span};
+use colored::{ColoredString, Colorize};
+use pest::error::{Error, ErrorVariant, InputLocation};
+use serde_json::{json, Value};
+
+/// Lines of a multi-line span that are shown before the rest is elided
+const SHOWN_SPAN_LINES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// How diagnostics are printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    /// Colored text with the source lines diagnostics point at
+    Human,
+    /// One JSON object per line, for editors and CI
+    Json,
+}
+
+/// A way to fix a diagnostic, rendered as a help line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    /// Code to replace and the text replacing it, when the fix can be applied as is
+    pub replacement: Option<(Span, String)>,
+}
+
+/// An error or a warning, rendered like ```rustc```'s with the source line it points at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Kind of the diagnostic, e.g. ```type-error```, stable across releases
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// Code the diagnostic is about, underlined when it's rendered
+    pub span: Option<Span>,
+    /// Text shown next to the underline
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message.into())
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message,
+            span: None,
+            label: None,
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(Suggestion {
+            message: help.into(),
+            replacement: None,
+        });
+        self
+    }
+
+    /// Adds a help line suggesting to replace the code at ```span``` with ```replacement```
+    pub fn with_suggestion(
+        mut self,
+        help: impl Into<String>,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.help.push(Suggestion {
+            message: help.into(),
+            replacement: Some((span, replacement.into())),
+        });
+        self
+    }
+
+    /// Describes a syntax error of the source file ```file```, with the tokens pest expected
+    pub fn from_syntax_error(err: &Error<Rule>, source: &str, file: FileId) -> Self {
+        let (start, end) = match err.location {
+            InputLocation::Pos(pos) => (pos, pos + found_token(source, pos).len()),
+            InputLocation::Span(span) => span,
+        };
+        let (line, column) = pest::Position::new(source, start.min(source.len()))
+            .map(|position| position.line_col())
+            .unwrap_or((1, 1));
+        let span = Span {
+            file,
+            start,
+            end,
+            line,
+            column,
+        };
+
+        let diagnostic = match &err.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                let found = match found_token(source, start) {
+                    "" => "end of file".to_string(),
+                    "\n" | "\r" => "end of line".to_string(),
+                    token => format!("`{}`", token),
+                };
+
+                if positives.is_empty() {
+                    Diagnostic::error(format!("unexpected {}", list_rules(negatives)))
+                        .with_span(span)
+                } else {
+                    let expected = list_rules(positives);
+
+                    Diagnostic::error(format!("expected {}, found {}", expected, found))
+                        .with_span(span)
+                        .with_label(format!("expected {}", expected))
+                }
+            }
+            ErrorVariant::CustomError { message } => Diagnostic::error(message).with_span(span),
+        };
+
+        diagnostic.with_code("syntax-error")
+    }
+
+    /// Renders the diagnostic, with the lines of its span taken from ```sources```
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut rendered = format!("{}: {}\n", self.severity_name(), self.message.bold());
+
+        let file = self
+            .span
+            .and_then(|span| Some((span, sources.get(span.file)?)));
+
+        // Gutter on the left of the source lines, as wide as the largest line number
+        let gutter_width = file.map_or(0, |(span, file)| {
+            let (last_line, _) = line_col(&file.source, span.end.max(span.start));
+            last_line.to_string().len()
+        });
+        let gutter = " ".repeat(gutter_width);
+        let bar = "|".blue().bold();
+
+        if let Some((span, file)) = file {
+            writeln!(
+                rendered,
+                "{}{} {}:{}:{}",
+                gutter,
+                "-->".blue().bold(),
+                file.path.display(),
+                span.line,
+                span.column
+            )
+            .unwrap();
+            writeln!(rendered, "{} {}", gutter, bar).unwrap();
+
+            self.render_span(&mut rendered, span, &file.source, gutter_width);
+        }
+
+        if !self.notes.is_empty() || !self.help.is_empty() {
+            if file.is_some() {
+                writeln!(rendered, "{} {}", gutter, bar).unwrap();
+            }
+
+            let notes = self.notes.iter().map(|note| ("note", note));
+            let help = self.help.iter().map(|help| ("help", &help.message));
+
+            for (kind, line) in notes.chain(help) {
+                writeln!(
+                    rendered,
+                    "{} {} {}: {}",
+                    gutter,
+                    "=".blue().bold(),
+                    kind.bold(),
+                    line
+                )
+                .unwrap();
+            }
+        }
+
+        rendered
+    }
+
+    /// Describes the diagnostic as a JSON object, with the paths of its spans taken from
+    /// ```sources```
+    pub fn to_json(&self, sources: &SourceMap) -> Value {
+        json!({
+            "severity": match self.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            "code": self.code,
+            "message": self.message,
+            "file": self
+                .span
+                .and_then(|span| sources.get(span.file))
+                .map(|file| file.path.display().to_string()),
+            "span": self.span.map(|span| span_to_json(span, sources)),
+            "label": self.label,
+            "notes": self.notes,
+            "suggestions": self
+                .help
+                .iter()
+                .map(|help| {
+                    let (span, replacement) = match &help.replacement {
+                        Some((span, replacement)) => {
+                            (Some(span_to_json(*span, sources)), Some(replacement))
+                        }
+                        None => (None, None),
+                    };
+
+                    json!({
+                        "message": help.message,
+                        "span": span,
+                        "replacement": replacement,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Prints the diagnostic to stderr
+    pub fn emit(&self, sources: &SourceMap, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", self.render(sources)),
+            ErrorFormat::Json => eprintln!("{}", self.to_json(sources)),
+        }
+    }
+
+    fn severity_name(&self) -> ColoredString {
+        match self.severity {
+            Severity::Error => "error".red().bold(),
+            Severity::Warning => "warning".yellow().bold(),
+        }
+    }
+
+    fn underline_color(&self, underline: &str) -> ColoredString {
+        match self.severity {
+            Severity::Error => underline.red().bold(),
+            Severity::Warning => underline.yellow().bold(),
+        }
+    }
+
+    /// Writes the source lines of a span with the span underlined
+    fn render_span(&self, rendered: &mut String, span: Span, source: &str, gutter_width: usize) {
+        let lines = source.lines().collect::<Vec<_>>();
+        let (start_line, start_column) = line_col(source, span.start);
+        // The last character of the span, which may take more than one byte
+        let last_char = source
+            .get(..span.end.min(source.len()))
+            .and_then(|text| text.char_indices().last())
+            .map_or(span.start, |(offset, _)| offset.max(span.start));
+        let (end_line, end_column) = line_col(source, last_char);
+
+        let bar = "|".blue().bold();
+        let label = self.label.as_ref().map_or(String::new(), |label| {
+            format!(" {}", self.underline_color(label))
+        });
+        let line_number = |line: usize| format!("{:>1$}", line, gutter_width).blue().bold();
+        let gutter = " ".repeat(gutter_width);
+        let source_line = |line: usize| expand_tabs(lines.get(line - 1).copied().unwrap_or(""));
+
+        if start_line == end_line {
+            let text = source_line(start_line);
+            let width = (end_column + 1).saturating_sub(start_column).max(1);
+
+            writeln!(rendered, "{} {} {}", line_number(start_line), bar, text).unwrap();
+            writeln!(
+                rendered,
+                "{} {} {}{}{}",
+                gutter,
+                bar,
+                " ".repeat(start_column),
+                self.underline_color(&"^".repeat(width)),
+                label
+            )
+            .unwrap();
+
+            return;
+        }
+
+        // Multi-line spans are drawn with a line joining their first and last characters
+        writeln!(
+            rendered,
+            "{} {}   {}",
+            line_number(start_line),
+            bar,
+            source_line(start_line)
+        )
+        .unwrap();
+        writeln!(
+            rendered,
+            "{} {}  {}",
+            gutter,
+            bar,
+            self.underline_color(&format!("{}^", "_".repeat(start_column + 1)))
+        )
+        .unwrap();
+
+        let joint = self.underline_color("|");
+
+        for line in start_line + 1..=end_line {
+            if line - start_line == SHOWN_SPAN_LINES && line < end_line {
+                writeln!(rendered, "{} {}", "...".blue().bold(), joint).unwrap();
+                continue;
+            } else if line - start_line > SHOWN_SPAN_LINES && line < end_line {
+                continue;
+            }
+
+            writeln!(
+                rendered,
+                "{} {} {} {}",
+                line_number(line),
+                bar,
+                joint,
+                source_line(line)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            rendered,
+            "{} {} {}{}",
+            gutter,
+            bar,
+            self.underline_color(&format!("|{}^", "_".repeat(end_column + 1))),
+            label
+        )
+        .unwrap();
+    }
+}
+
+/// A source file diagnostics can point into
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+}
+
+/// Source files of a project, indexed by the ids their spans point to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Adds a file and returns its id
+    pub fn add(&mut self, path: PathBuf, source: String) -> FileId {
+        self.files.push(SourceFile { path, source });
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+}
+
+/// Describes a span as a JSON object, lines and columns start at 1 and columns count characters
+fn span_to_json(span: Span, sources: &SourceMap) -> Value {
+    let end = sources
+        .get(span.file)
+        .and_then(|file| pest::Position::new(&file.source, span.end))
+        .map(|position| position.line_col());
+
+    json!({
+        "byte_start": span.start,
+        "byte_end": span.end,
+        "line_start": span.line,
+        "column_start": span.column,
+        "line_end": end.map(|(line, _)| line),
+        "column_end": end.map(|(_, column)| column),
+    })
+}
+
+/// Returns the line of a byte offset, starting at 1, and its column on screen, starting at 0
+///
+/// Offsets inside a character are moved back to its start
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let line_start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+
+    (
+        source[..line_start].matches('\n').count() + 1,
+        expand_tabs(&source[line_start..offset]).chars().count(),
+    )
+}
+
+/// Replaces tabs with spaces, so underlines line up with the text above them
+fn expand_tabs(text: &str) -> String {
+    text.trim_end_matches('\r').replace('\t', "    ")
+}
+
+/// Returns the token at a byte offset, a word or a single character
+fn found_token(source: &str, offset: usize) -> &str {
+    let rest = source.get(offset..).unwrap_or_default();
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    match rest.chars().next() {
+        None => "",
+        Some(first) if is_word_char(first) => {
+            &rest[..rest.find(|c| !is_word_char(c)).unwrap_or(rest.len())]
+        }
+        Some(first) => &rest[..first.len_utf8()],
+    }
+}
+
+/// Lists rules the way they're written in the source, e.g. ```one of `:`, `(` or name```
+fn list_rules(rules: &[Rule]) -> String {
+    let mut descriptions = vec![];
+
+    for rule in rules {
+        let description = describe_rule(*rule);
+
+        if !descriptions.contains(&description) {
+            descriptions.push(description);
+        }
+    }
+
+    match descriptions.as_slice() {
+        [] => "nothing".to_string(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("one of {} or {}", rest.join(", "), last),
+    }
+}
+
+/// Describes what a rule matches, rules starting with a keyword or punctuation are described
+/// by it
+fn describe_rule(rule: Rule) -> String {
+    match rule {
+        Rule::EOI => "end of file",
+        Rule::name | Rule::fn_name | Rule::qualified_name | Rule::pattern | Rule::match_arm => {
+            "name"
+        }
+        Rule::module_name => "module name",
+        Rule::type_name | Rule::firework_type | Rule::pointer_type => "type",
+        Rule::type_signature => "`:`",
+        Rule::fn_args => "arguments",
+        Rule::variadic => "`...`",
+        Rule::export_annotation => "`export`",
+        Rule::declaration | Rule::let_binding => "`let`",
+        Rule::extern_declaration => "`extern`",
+        Rule::module_declaration => "`module`",
+        Rule::module_import => "`import`",
+        Rule::enum_type => "`enum`",
+        Rule::import_alias => "`as`",
+        Rule::name_list => "`(`",
+        Rule::int
+        | Rule::float
+        | Rule::boolean
+        | Rule::string
+        | Rule::char
+        | Rule::list
+        | Rule::unit
+        | Rule::literal
+        | Rule::anon_fn
+        | Rule::precedence
+        | Rule::identifier
+        | Rule::if_statement
+        | Rule::match_expr
+        | Rule::do_block
+        | Rule::try_expr => "expression",
+        rule => return format!("{:?}", rule).replace('_', " "),
+    }
+    .to_string()
+}
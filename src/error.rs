@@ -0,0 +1,108 @@
+//! Errors of the compiler
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::diagnostics::Diagnostic;
+
+/// Reason compiling, building or running a Firework project failed
+#[derive(Debug)]
+pub enum FireworkError {
+    /// A file couldn't be read or written
+    Io {
+        /// File the operation was about, if there was one
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The source code has syntax errors
+    Parse(Vec<Diagnostic>),
+    /// The program is ill-formed, e.g. it has mismatched types or uses a name that doesn't exist
+    ///
+    /// The diagnostic is boxed so that results carrying the error stay small
+    Type(Box<Diagnostic>),
+    /// LLVM or the linker failed, or the program uses a feature that isn't implemented
+    Codegen(String),
+    /// The core library couldn't be downloaded or installed
+    CoreInstall(String),
+}
+
+impl FireworkError {
+    /// Returns an error about a file
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        FireworkError::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    /// Returns the diagnostics describing the error
+    ///
+    /// Diagnostics without a code get the code of the kind of error
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let diagnostics = match self {
+            FireworkError::Parse(diagnostics) => diagnostics.clone(),
+            FireworkError::Type(diagnostic) => vec![(**diagnostic).clone()],
+            error => vec![Diagnostic::error(error.to_string())],
+        };
+
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| match diagnostic.code {
+                Some(_) => diagnostic,
+                None => diagnostic.with_code(self.code()),
+            })
+            .collect()
+    }
+
+    /// Returns the code of the kind of error, e.g. ```type-error```
+    pub fn code(&self) -> &'static str {
+        match self {
+            FireworkError::Io { .. } => "io-error",
+            FireworkError::Parse(_) => "syntax-error",
+            FireworkError::Type(_) => "type-error",
+            FireworkError::Codegen(_) => "codegen-error",
+            FireworkError::CoreInstall(_) => "core-install-error",
+        }
+    }
+}
+
+impl fmt::Display for FireworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FireworkError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "Couldn't access {}: {}", path.display(), source),
+            FireworkError::Io { path: None, source } => write!(f, "{}", source),
+            FireworkError::Parse(diagnostics) => match diagnostics.as_slice() {
+                [diagnostic] => write!(f, "{}", diagnostic.message),
+                diagnostics => write!(f, "{} syntax errors", diagnostics.len()),
+            },
+            FireworkError::Type(diagnostic) => write!(f, "{}", diagnostic.message),
+            FireworkError::Codegen(message) | FireworkError::CoreInstall(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FireworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FireworkError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FireworkError {
+    fn from(source: io::Error) -> Self {
+        FireworkError::Io { path: None, source }
+    }
+}
+
+impl From<Diagnostic> for FireworkError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        FireworkError::Type(Box::new(diagnostic))
+    }
+}
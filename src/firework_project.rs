@@ -1,112 +1,610 @@
 //! Firework Project
 extern crate inkwell_llvm12 as inkwell;
 
-use indoc::formatdoc;
-use inkwell::support::LLVMString;
-use std::fs::{self, File};
-use std::io::BufRead;
-use std::io::BufReader;
+use std::cell::{Ref, RefCell};
+use std::fs::{self};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
 
 use crate::codegen::CodeGen;
-use crate::parser::parse;
-use crate::unrecoverable_error;
+use crate::diagnostics::{Diagnostic, SourceMap};
+use crate::error::FireworkError;
+use crate::parser::{parse_recovering, tokenize, AstNode, SourceModule, Span, TokenKind};
+
+/// Artifact the compiler can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The AST of every module
+    Ast,
+    /// The tokens of every module
+    Tokens,
+    /// LLVM IR
+    Ir,
+    /// LLVM bitcode
+    Bitcode,
+    /// Assembly for the target
+    Asm,
+    /// Object file for the target
+    Object,
+    /// Executable, or library when building one
+    Exe,
+}
+
+impl Emit {
+    /// Extension of the artifact's file
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Ast => "ast",
+            Emit::Tokens => "tokens",
+            Emit::Ir => "ll",
+            Emit::Bitcode => "bc",
+            Emit::Asm => "s",
+            Emit::Object => "o",
+            Emit::Exe => "",
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ast" => Ok(Emit::Ast),
+            "tokens" => Ok(Emit::Tokens),
+            "ir" => Ok(Emit::Ir),
+            "bc" => Ok(Emit::Bitcode),
+            "asm" => Ok(Emit::Asm),
+            "obj" => Ok(Emit::Object),
+            "exe" => Ok(Emit::Exe),
+            _ => Err(format!(
+                "Unknown artifact `{}`, expected one of ast, tokens, ir, bc, asm, obj, exe",
+                name
+            )),
+        }
+    }
+}
+
+/// Where an artifact is written
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// ```target/<project>``` with the artifact's extension
+    Default,
+    /// Standard output, written as ```-```
+    Stdout,
+    File(PathBuf),
+}
+
+impl From<&str> for Output {
+    fn from(path: &str) -> Self {
+        if path == "-" {
+            Output::Stdout
+        } else {
+            Output::File(PathBuf::from(path))
+        }
+    }
+}
+
+/// Reads the artifacts requested with ```--emit``` and ```-o```, an executable by default
+///
+/// Each value of ```--emit``` is an artifact's kind or ```kind=path```, ```-o``` gives the path
+/// of the only artifact that is requested
+pub fn emit_requests(emit: &[&str], output: Option<&str>) -> Result<Vec<(Emit, Output)>, String> {
+    let mut requests = if emit.is_empty() {
+        vec![(Emit::Exe, Output::Default)]
+    } else {
+        emit.iter()
+            .map(|value| match value.split_once('=') {
+                Some((emit, path)) => Ok((emit.parse()?, Output::from(path))),
+                None => Ok((value.parse()?, Output::Default)),
+            })
+            .collect::<Result<Vec<_>, String>>()?
+    };
+
+    if let Some(path) = output {
+        match &mut requests[..] {
+            [(_, output @ Output::Default)] => *output = Output::from(path),
+            [_] => return Err("-o conflicts with the path given to --emit".to_string()),
+            _ => {
+                return Err(
+                    "-o can only be used when emitting a single artifact, give each \
+                            one a path with --emit kind=path"
+                        .to_string(),
+                )
+            }
+        }
+    }
+
+    Ok(requests)
+}
+
+/// Kind of library a project can be built as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LibraryKind {
+    /// A ```.a``` archive linked into programs at build time
+    Static,
+    /// A ```.so``` shared object loaded by programs at run time
+    Dynamic,
+}
 
 /// Represents a Firework project
 pub struct FireworkProject<'ctx> {
     /// Compiler used to compile the code of the current Firework project
     compiler: CodeGen<'ctx>,
+    /// Source files of the loaded modules, which diagnostics point into
+    sources: RefCell<SourceMap>,
 }
 
 #[allow(dead_code)]
 impl<'ctx> FireworkProject<'ctx> {
     /// Instatiates a new ```FireworkProject``` struct
     pub fn new(compiler: CodeGen<'ctx>) -> Self {
-        Self { compiler }
+        Self {
+            compiler,
+            sources: RefCell::new(SourceMap::default()),
+        }
     }
 
     /// Creates a new project
-    pub fn new_project(&self, project_name: &str) {
+    pub fn new_project(&self, project_name: &str) -> Result<(), FireworkError> {
         self.create_project(project_name)
-            .unwrap_or_else(|err| unrecoverable_error!(err.to_string()));
+            .map_err(|err| FireworkError::io(project_name, err))
     }
 
     /// Compiles the current project's code
-    pub fn compile(&self) -> std::io::Result<()> {
-        let main = fs::read_to_string("src/main.firework").unwrap_or_else(|_| {
-            unrecoverable_error!("Couldn't read src/main.firework or project not found")
-        });
+    pub fn compile(&self) -> Result<(), FireworkError> {
+        self.compiler.compile(self.load_modules()?)
+    }
 
-        let parsed = parse(&main).unwrap_or_else(|err| {
-            let col = match err.line_col {
-                pest::error::LineColLocation::Pos(a) => a,
-                _ => unreachable!(),
-            };
+    /// Returns the source files of the loaded modules, which diagnostics point into
+    pub fn sources(&self) -> Ref<'_, SourceMap> {
+        self.sources.borrow()
+    }
 
-            let location = match err.location {
-                pest::error::InputLocation::Pos(a) => a,
-                _ => unreachable!(),
-            };
+    /// Returns the warnings reported since the last call
+    pub fn take_warnings(&self) -> Vec<Diagnostic> {
+        self.compiler.take_diagnostics()
+    }
 
-            let file = File::open("src/main.firework").unwrap();
-
-            let reader = BufReader::new(file);
-            let line = reader
-                .lines()
-                .enumerate()
-                .filter(|(index, _)| index == &(col.0 - 1))
-                .map(|(_, line)| line.unwrap())
-                .collect::<String>();
-
-            unrecoverable_error!(formatdoc! {
-            "Syntax error at src/main.firework:{line_number}:{col}
-                {empty_space}| 
-                {empty_space}| {line}
-                {empty_space}|
-                ",
-            line_number = col.0,
-            col = location,
-            line = line,
-            empty_space = "  ",
-            });
-        });
+    /// Parses the project's main module and every module it imports
+    ///
+    /// The syntax errors of every module are reported together
+    fn load_modules(&self) -> Result<Vec<SourceModule>, FireworkError> {
+        self.load_modules_from(Path::new(""))
+    }
 
-        self.compiler.compile(parsed);
+    /// Parses the main module and every module it imports of the project in ```root```
+    pub(crate) fn load_modules_from(
+        &self,
+        root: &Path,
+    ) -> Result<Vec<SourceModule>, FireworkError> {
+        let mut modules = vec![];
+        let mut syntax_errors = vec![];
+        self.load_module(
+            root,
+            "main",
+            None,
+            &mut modules,
+            &mut vec![],
+            &mut syntax_errors,
+        )?;
 
-        Ok(())
+        if !syntax_errors.is_empty() {
+            return Err(FireworkError::Parse(syntax_errors));
+        }
+
+        Ok(modules)
     }
 
-    /// Runs the current project's code
-    pub fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
-        self.compile()?;
-        unsafe {
-            self.compiler.call_main();
+    /// Parses a module and, before it, every module it imports, ```imported_at``` is the span
+    /// of the import that loads it
+    ///
+    /// Modules that were already loaded are skipped, so each module is only compiled once
+    fn load_module(
+        &self,
+        root: &Path,
+        name: &str,
+        imported_at: Option<Span>,
+        modules: &mut Vec<SourceModule>,
+        import_stack: &mut Vec<String>,
+        syntax_errors: &mut Vec<Diagnostic>,
+    ) -> Result<(), FireworkError> {
+        if modules.iter().any(|module| module.name == name) {
+            return Ok(());
+        }
+
+        let import_error = |message: String| {
+            let diagnostic = Diagnostic::error(message);
+
+            match imported_at {
+                Some(span) => diagnostic.with_span(span),
+                None => diagnostic,
+            }
         };
+
+        if let Some(index) = import_stack.iter().position(|module| module == name) {
+            return Err(import_error(format!(
+                "Import cycle detected: {} -> {}",
+                import_stack[index..].join(" -> "),
+                name
+            ))
+            .into());
+        }
+
+        let path = root.join(module_path(name));
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) if imported_at.is_some() => {
+                return Err(import_error(format!(
+                    "Couldn't find module `{}`, expected it at {}",
+                    name,
+                    path.display()
+                ))
+                .with_note(err.to_string())
+                .into())
+            }
+            Err(err) => return Err(FireworkError::io(path, err)),
+        };
+
+        let id = self.sources.borrow_mut().add(path.clone(), source.clone());
+        // The statements that parse are still loaded, so the modules they import get checked
+        let (ast, diagnostics) = parse_recovering(&source, id);
+        syntax_errors.extend(diagnostics);
+
+        import_stack.push(name.to_string());
+
+        for node in &ast {
+            match node {
+                AstNode::ModuleImport { module, span, .. } => {
+                    if let AstNode::Module(import, _) = &**module {
+                        self.load_module(
+                            root,
+                            import,
+                            Some(*span),
+                            modules,
+                            import_stack,
+                            syntax_errors,
+                        )?;
+                    }
+                }
+                AstNode::ModuleDeclaration { module, span, .. } => {
+                    if let AstNode::Module(declared, _) = &**module {
+                        if name != "main" && declared != name {
+                            return Err(Diagnostic::error(format!(
+                                "{} declares module `{}` but was imported as `{}`",
+                                path.display(),
+                                declared,
+                                name
+                            ))
+                            .with_span(*span)
+                            .into());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        import_stack.pop();
+
+        modules.push(SourceModule {
+            id,
+            name: name.to_string(),
+            path,
+            source,
+            ast,
+        });
+
         Ok(())
     }
 
+    /// Runs the current project's code, compiled by ```compile```, and returns its exit code
+    pub fn run(&self) -> Result<i32, FireworkError> {
+        if self.compiler.get_function("main").is_none() {
+            return Err(Diagnostic::error(
+                "Can't run the project, src/main.firework has no `main`",
+            )
+            .into());
+        }
+
+        self.compiler.optimize();
+        let exit_code = unsafe { self.compiler.call_main() };
+        Ok(exit_code)
+    }
+
     /// Creates a new Firework project
     fn create_project(&self, project_name: &str) -> std::io::Result<()> {
         fs::create_dir_all(project_name)?;
 
-        fs::write(format!("{}/.gitignore", project_name), "ir.ll\nout.asm")?;
+        fs::write(
+            format!("{}/.gitignore", project_name),
+            "ir.ll\nout.asm\ntarget/",
+        )?;
         fs::create_dir_all(format!("{}/src", project_name))?;
         fs::write(
             format!("{}/src/main.firework", project_name),
-            "let main: i64 = printf \"Hello World!\"",
+            "let main: Unit = puts(\"Hello World!\")",
         )?;
 
         Ok(())
     }
 
-    /// Dumps the code of the current project as LLVM IR
-    pub fn dump_ir(&self) -> Result<(), LLVMString> {
-        self.compile().unwrap();
-        self.compiler.dump_ir()
+    /// Writes a C header declaring the project's exported functions and returns its path
+    ///
+    /// The header is written to ```target/<project>.h``` unless another path is given
+    pub fn header(&self, output: Option<&Path>) -> Result<PathBuf, FireworkError> {
+        let modules = self.load_modules()?;
+        self.compiler.compile(modules.clone())?;
+
+        let name = project_name()?;
+        let path = match output {
+            Some(output) => output.to_path_buf(),
+            None => Path::new("target").join(format!("{}.h", name)),
+        };
+
+        let guard = format!("{}_H", name)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        write_file(&path, self.compiler.c_header(&modules, &guard))?;
+
+        Ok(path)
+    }
+
+    /// Builds the current project as an executable in ```target/``` and returns its path
+    ///
+    /// WebAssembly targets are built as a ```.wasm``` module whose ```_start``` runs main
+    pub fn build(&self) -> Result<PathBuf, FireworkError> {
+        Ok(self.emit(&[(Emit::Exe, Output::Default)], None)?.remove(0))
+    }
+
+    /// Builds the current project as a library in ```target/``` and returns its path
+    ///
+    /// Only the project's exported functions are visible to the programs linking it
+    pub fn build_library(&self, kind: LibraryKind) -> Result<PathBuf, FireworkError> {
+        Ok(self
+            .emit(&[(Emit::Exe, Output::Default)], Some(kind))?
+            .remove(0))
+    }
+
+    /// Compiles the current project once and writes every requested artifact, returns the paths
+    /// of the files that were written
+    ///
+    /// ```Emit::Exe``` is a library instead of an executable when a library kind is given
+    pub fn emit(
+        &self,
+        requests: &[(Emit, Output)],
+        library: Option<LibraryKind>,
+    ) -> Result<Vec<PathBuf>, FireworkError> {
+        let modules = self.load_modules()?;
+        let name = project_name()?;
+
+        // The AST and the tokens only need the program to parse, so they can be emitted from
+        // programs with type errors
+        let needs_compiling = requests
+            .iter()
+            .any(|(emit, _)| !matches!(emit, Emit::Ast | Emit::Tokens));
+
+        if needs_compiling {
+            self.compiler.compile(modules.clone())?;
+
+            // Internal symbols that aren't used are removed by the optimization passes
+            if library.is_some() {
+                self.compiler.hide_internal_symbols();
+            }
+
+            self.compiler.optimize();
+        }
+
+        let mut written = vec![];
+
+        for (emit, output) in requests {
+            let path = match output {
+                Output::Default => Some(self.default_path(*emit, &name, library)),
+                Output::File(path) => Some(path.clone()),
+                Output::Stdout => None,
+            };
+
+            let contents = match emit {
+                Emit::Ast => modules
+                    .iter()
+                    .map(|module| format!("// {}\n{:#?}\n", module.path.display(), module.ast))
+                    .collect::<String>()
+                    .into_bytes(),
+                Emit::Tokens => {
+                    let mut tokens = String::new();
+
+                    for module in &modules {
+                        for token in tokenize(&module.source)? {
+                            let kind = match token.kind {
+                                TokenKind::Rule(rule) => format!("{:?}", rule),
+                                TokenKind::Keyword => "keyword".to_string(),
+                                TokenKind::Punctuation => "punctuation".to_string(),
+                            };
+
+                            tokens += &format!(
+                                "{}:{}:{} {} {:?}\n",
+                                module.path.display(),
+                                token.location.line,
+                                token.location.column,
+                                kind,
+                                token.text
+                            );
+                        }
+                    }
+
+                    tokens.into_bytes()
+                }
+                Emit::Ir => self.compiler.ir().into_bytes(),
+                Emit::Bitcode => self.compiler.bitcode(),
+                Emit::Asm => self.compiler.assembly()?,
+                Emit::Object => self.compiler.object()?,
+                Emit::Exe => {
+                    // Executables are written by the linker, so one written to stdout is linked
+                    // to its default path first
+                    let path = path
+                        .clone()
+                        .unwrap_or_else(|| self.default_path(Emit::Exe, &name, library));
+                    self.link(&name, &path, library)?;
+
+                    match output {
+                        Output::Stdout => {
+                            fs::read(&path).map_err(|err| FireworkError::io(&path, err))?
+                        }
+                        _ => {
+                            written.push(path);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            match path {
+                Some(path) => {
+                    write_file(&path, contents)?;
+                    written.push(path);
+                }
+                None => io::stdout().write_all(&contents)?,
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Returns the path an artifact is written to unless another one is given
+    fn default_path(&self, emit: Emit, name: &str, library: Option<LibraryKind>) -> PathBuf {
+        let file_name = match (emit, library) {
+            (Emit::Exe, Some(LibraryKind::Static)) => format!("lib{}.a", name),
+            (Emit::Exe, Some(LibraryKind::Dynamic)) => format!("lib{}.so", name),
+            (Emit::Exe, None) if self.compiler.is_wasm_target() => format!("{}.wasm", name),
+            (Emit::Exe, None) => name.to_string(),
+            (emit, _) => format!("{}.{}", name, emit.extension()),
+        };
+
+        Path::new("target").join(file_name)
+    }
+
+    /// Links the compiled code into an executable or a library
+    fn link(
+        &self,
+        name: &str,
+        path: &Path,
+        library: Option<LibraryKind>,
+    ) -> Result<(), FireworkError> {
+        if library.is_none() && self.compiler.get_function("main").is_none() {
+            return Err(Diagnostic::error(
+                "Can't build an executable, src/main.firework has no `main`",
+            )
+            .into());
+        }
+
+        let object = Path::new("target").join(format!("{}.o", name));
+        write_file(&object, self.compiler.object()?)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| FireworkError::io(parent, err))?;
+        }
+
+        let mut command = match library {
+            Some(LibraryKind::Static) => {
+                // ar adds to existing archives instead of replacing them
+                if path.exists() {
+                    fs::remove_file(path).map_err(|err| FireworkError::io(path, err))?;
+                }
+
+                let mut command = Command::new("ar");
+                command.arg("rcs").arg(path).arg(&object);
+                command
+            }
+            Some(LibraryKind::Dynamic) => {
+                let mut command = Command::new(c_compiler());
+                command.arg("-shared").arg(&object).arg("-o").arg(path);
+                command
+            }
+            None if self.compiler.is_wasm_target() => {
+                let mut command = Command::new(wasm_linker());
+                command.arg(&object).arg("-o").arg(path);
+
+                // Modules that don't target WASI are called from JavaScript, they have no entry
+                // point
+                let is_wasi = matches!(
+                    self.compiler.target().triple.as_deref(),
+                    Some(triple) if triple.ends_with("-wasi") || triple.ends_with("-wasip1")
+                );
+                if !is_wasi {
+                    command.arg("--no-entry");
+                }
+
+                command
+            }
+            None => {
+                let mut command = Command::new(c_compiler());
+                command.arg(&object).arg("-o").arg(path);
+                command
+            }
+        };
+
+        run_command(&mut command)
+    }
+}
+
+/// Returns the name of the project, which is the name of its directory
+fn project_name() -> std::io::Result<String> {
+    Ok(std::env::current_dir()?
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "main".to_string()))
+}
+
+/// Returns the C compiler used to link, ```$CC``` or ```cc```
+///
+/// Cross-compiled projects need a compiler for their target, e.g. ```CC=aarch64-linux-gnu-gcc```
+fn c_compiler() -> String {
+    std::env::var("CC").unwrap_or_else(|_| "cc".to_string())
+}
+
+/// Returns the linker of WebAssembly modules, ```$WASM_LD``` or ```wasm-ld```
+fn wasm_linker() -> String {
+    std::env::var("WASM_LD").unwrap_or_else(|_| "wasm-ld".to_string())
+}
+
+/// Runs a command from the system's toolchain, failing if it doesn't exit successfully
+fn run_command(command: &mut Command) -> Result<(), FireworkError> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let status = command
+        .status()
+        .map_err(|err| FireworkError::Codegen(format!("Couldn't run `{}`: {}", program, err)))?;
+
+    if !status.success() {
+        return Err(FireworkError::Codegen(format!(
+            "`{}` failed with {}",
+            program, status
+        )));
     }
 
-    /// Dumps the code of the current project as AT&T Assembly
-    pub fn dump_asm(&self) -> Result<(), LLVMString> {
-        self.compile().unwrap();
-        self.compiler.dump_asm()
+    Ok(())
+}
+
+/// Writes a file, creating the directories it's in
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), FireworkError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| FireworkError::io(parent, err))?;
     }
+
+    fs::write(path, contents).map_err(|err| FireworkError::io(path, err))
+}
+
+/// Returns the path of a module's source file, ```foo::bar``` lives in ```src/foo/bar.firework```
+fn module_path(name: &str) -> PathBuf {
+    Path::new("src").join(format!("{}.firework", name.replace("::", "/")))
 }
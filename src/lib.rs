@@ -2,39 +2,1105 @@
 extern crate pest_derive;
 pub mod codegen;
 pub mod core;
+pub mod debug_info;
+pub mod diagnostics;
+pub mod error;
 pub mod firework_project;
+pub mod modules;
 pub mod parser;
 
-/// Used when code encounters an unrecoverable error.
-///
-/// Displays all errors given then panics
-#[macro_export]
-macro_rules! unrecoverable_error {
-    ( $( $errors:expr ),+ ) => {{
-        use colored::Colorize;
-        $(
-            println!("{}: {}", "error".red().bold(), $errors.white());
-        )*
-        panic!();
-    }};
-
-    () => {};
-}
-
-/// Used as a placeholder for unfinished code.
-///
-/// Calls the ```unrecoverable_error``` macro for every to-do feature given
-#[macro_export]
-macro_rules! todo_feature {
-    ( $( $features:expr ),+ ) => {{
-        $(
-            crate::unrecoverable_error!(format!("{} is not implemented yet!", $features));
-        )*
-    }}
-}
-
 #[cfg(test)]
 mod tests {
-    mod parser_tests {}
-    mod codegen_tests {}
+    mod parser_tests {
+        use crate::parser::{parse, tokenize, AstNode, Rule, Span, TokenKind};
+
+        fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+            Span {
+                file: 0,
+                start,
+                end,
+                line,
+                column,
+            }
+        }
+
+        #[test]
+        fn parses_do_blocks() {
+            let ast = parse("let main: i64 = do { puts(\"a\"); let x: i64 = 1; x }").unwrap();
+
+            assert_eq!(
+                ast,
+                vec![AstNode::Fn {
+                    name: "main".to_string(),
+                    return_type: Box::new(AstNode::Type("i64".to_string(), span(10, 13, 1, 11))),
+                    args: Box::new(AstNode::FnArgs(vec![], span(8, 8, 1, 9))),
+                    value: Box::new(AstNode::Do(
+                        vec![
+                            AstNode::Identifier {
+                                name: "puts".to_string(),
+                                args: vec![AstNode::Str("a".to_string(), span(26, 29, 1, 27))],
+                                span: span(21, 30, 1, 22),
+                            },
+                            AstNode::LetBinding {
+                                name: "x".to_string(),
+                                value_type: Some(Box::new(AstNode::Type(
+                                    "i64".to_string(),
+                                    span(39, 42, 1, 40)
+                                ))),
+                                value: Box::new(AstNode::Int(1, span(45, 46, 1, 46))),
+                                span: span(32, 46, 1, 33),
+                            },
+                            AstNode::Identifier {
+                                name: "x".to_string(),
+                                args: vec![],
+                                span: span(48, 49, 1, 49),
+                            },
+                        ],
+                        span(16, 51, 1, 17)
+                    )),
+                    exported: false,
+                    span: span(0, 51, 1, 1),
+                }]
+            );
+        }
+
+        #[test]
+        fn parses_module_imports_and_exports() {
+            let ast = parse("module foo exports (a, b)\nimport foo::bar as baz (c)\n").unwrap();
+
+            assert_eq!(
+                ast,
+                vec![
+                    AstNode::ModuleDeclaration {
+                        module: Box::new(AstNode::Module("foo".to_string(), span(7, 10, 1, 8))),
+                        exports: Some(vec!["a".to_string(), "b".to_string()]),
+                        span: span(0, 25, 1, 1),
+                    },
+                    AstNode::ModuleImport {
+                        module: Box::new(AstNode::Module(
+                            "foo::bar".to_string(),
+                            span(33, 41, 2, 8)
+                        )),
+                        alias: Some("baz".to_string()),
+                        names: Some(vec!["c".to_string()]),
+                        span: span(26, 52, 2, 1),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn parses_sum_types_match_and_try() {
+            let ast = parse(
+                "let f(o: Option i64 r: Result i64 str): i64 = match o { Some(x) -> x, _ -> r? }",
+            )
+            .unwrap();
+
+            assert_eq!(
+                ast,
+                vec![AstNode::Fn {
+                    name: "f".to_string(),
+                    return_type: Box::new(AstNode::Type("i64".to_string(), span(40, 43, 1, 41))),
+                    args: Box::new(AstNode::FnArgs(
+                        vec![
+                            (
+                                AstNode::Identifier {
+                                    name: "o".to_string(),
+                                    args: vec![],
+                                    span: span(6, 7, 1, 7),
+                                },
+                                AstNode::GenericType {
+                                    name: "Option".to_string(),
+                                    args: vec![AstNode::Type(
+                                        "i64".to_string(),
+                                        span(16, 19, 1, 17)
+                                    )],
+                                    span: span(9, 19, 1, 10),
+                                },
+                            ),
+                            (
+                                AstNode::Identifier {
+                                    name: "r".to_string(),
+                                    args: vec![],
+                                    span: span(20, 21, 1, 21),
+                                },
+                                AstNode::GenericType {
+                                    name: "Result".to_string(),
+                                    args: vec![
+                                        AstNode::Type("i64".to_string(), span(30, 33, 1, 31)),
+                                        AstNode::Type("str".to_string(), span(34, 37, 1, 35)),
+                                    ],
+                                    span: span(23, 37, 1, 24),
+                                },
+                            ),
+                        ],
+                        span(6, 37, 1, 7)
+                    )),
+                    value: Box::new(AstNode::Match {
+                        value: Box::new(AstNode::Identifier {
+                            name: "o".to_string(),
+                            args: vec![],
+                            span: span(52, 53, 1, 53),
+                        }),
+                        arms: vec![
+                            (
+                                AstNode::Pattern {
+                                    constructor: "Some".to_string(),
+                                    binding: Some("x".to_string()),
+                                    span: span(56, 63, 1, 57),
+                                },
+                                AstNode::Identifier {
+                                    name: "x".to_string(),
+                                    args: vec![],
+                                    span: span(67, 68, 1, 68),
+                                },
+                            ),
+                            (
+                                AstNode::Pattern {
+                                    constructor: "_".to_string(),
+                                    binding: None,
+                                    span: span(70, 71, 1, 71),
+                                },
+                                AstNode::Try(
+                                    Box::new(AstNode::Identifier {
+                                        name: "r".to_string(),
+                                        args: vec![],
+                                        span: span(75, 76, 1, 76),
+                                    }),
+                                    span(75, 77, 1, 76)
+                                ),
+                            ),
+                        ],
+                        span: span(46, 79, 1, 47),
+                    }),
+                    exported: false,
+                    span: span(0, 79, 1, 1),
+                }]
+            );
+        }
+
+        #[test]
+        fn parses_extern_declarations() {
+            let ast = parse("extern \"C\" let printf(format: *i8 ...): i32").unwrap();
+
+            assert_eq!(
+                ast,
+                vec![AstNode::ExternFn {
+                    abi: "C".to_string(),
+                    name: "printf".to_string(),
+                    args: Box::new(AstNode::FnArgs(
+                        vec![(
+                            AstNode::Identifier {
+                                name: "format".to_string(),
+                                args: vec![],
+                                span: span(22, 28, 1, 23),
+                            },
+                            AstNode::PointerType(
+                                Box::new(AstNode::Type("i8".to_string(), span(31, 33, 1, 32))),
+                                span(30, 33, 1, 31)
+                            ),
+                        )],
+                        span(22, 33, 1, 23)
+                    )),
+                    return_type: Box::new(AstNode::Type("i32".to_string(), span(40, 43, 1, 41))),
+                    variadic: true,
+                    span: span(0, 43, 1, 1),
+                }]
+            );
+        }
+
+        #[test]
+        fn spans_nodes_in_their_file() {
+            let ast = crate::parser::parse_file("let main: Unit = puts(\"a\")", 2).unwrap();
+
+            assert_eq!(
+                ast[0].span(),
+                Span {
+                    file: 2,
+                    ..span(0, 26, 1, 1)
+                }
+            );
+        }
+
+        #[test]
+        fn recovers_from_syntax_errors() {
+            let source = "let a i64 = 1\nlet f(x: i64): i64 = do {\n    let y: i64 = +(x, );\n    y\n}\nlet é: i64 = 2\nlet b: i64 = 3\n";
+            let (ast, diagnostics) = crate::parser::parse_recovering(source, 0);
+
+            assert_eq!(
+                ast,
+                vec![AstNode::Fn {
+                    name: "b".to_string(),
+                    return_type: Box::new(AstNode::Type("i64".to_string(), span(96, 99, 7, 8))),
+                    args: Box::new(AstNode::FnArgs(vec![], span(94, 94, 7, 6))),
+                    value: Box::new(AstNode::Int(3, span(102, 103, 7, 14))),
+                    exported: false,
+                    span: span(89, 103, 7, 1),
+                }]
+            );
+            assert_eq!(
+                diagnostics
+                    .iter()
+                    .map(|diagnostic| (diagnostic.message.as_str(), diagnostic.span.unwrap().line))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("expected `:`, found `i64`", 1),
+                    ("expected expression, found `)`", 3),
+                    ("expected name, found `é`", 6),
+                ]
+            );
+        }
+
+        #[test]
+        fn reports_list_literals_as_unimplemented() {
+            let diagnostics = parse("let a: i64 = 1\nlet l: i64 = [1,2]\n")
+                .unwrap_err()
+                .diagnostics();
+
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(
+                diagnostics[0].message,
+                "List literals are not implemented yet"
+            );
+            assert_eq!(diagnostics[0].span, Some(span(28, 33, 2, 14)));
+        }
+
+        #[test]
+        fn tokenizes_keywords_and_punctuation() {
+            let tokens = tokenize("let x: i64 = /* one */ 1")
+                .unwrap()
+                .into_iter()
+                .map(|token| (token.kind, token.text, token.location.column))
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                tokens,
+                vec![
+                    (TokenKind::Keyword, "let".to_string(), 1),
+                    (TokenKind::Rule(Rule::fn_name), "x".to_string(), 5),
+                    (TokenKind::Punctuation, ":".to_string(), 6),
+                    (TokenKind::Rule(Rule::type_name), "i64".to_string(), 8),
+                    (TokenKind::Punctuation, "=".to_string(), 12),
+                    (TokenKind::Rule(Rule::int), "1".to_string(), 24),
+                ]
+            );
+        }
+    }
+    mod diagnostics_tests {
+        use crate::diagnostics::{Diagnostic, SourceMap};
+        use crate::parser::{parse, Span};
+        use serde_json::json;
+        use std::path::PathBuf;
+
+        fn render(source: &str, diagnostic: impl FnOnce(&str) -> Diagnostic) -> String {
+            colored::control::set_override(false);
+
+            let mut sources = SourceMap::default();
+            sources.add(PathBuf::from("src/main.firework"), source.to_string());
+
+            diagnostic(source).render(&sources)
+        }
+
+        #[test]
+        fn renders_syntax_errors_at_their_column() {
+            let source = "let x: i64 = 1\n\tlet main i64 = x";
+            let rendered = render(source, |source| {
+                parse(source).unwrap_err().diagnostics().remove(0)
+            });
+
+            assert_eq!(
+                rendered,
+                "error: expected `:`, found `i64`\n \
+                 --> src/main.firework:2:11\n  \
+                   |\n\
+                 2 |     let main i64 = x\n  \
+                   |              ^^^ expected `:`\n"
+            );
+        }
+
+        #[test]
+        fn renders_spans_ending_in_multi_byte_characters() {
+            let source = "let é: i64 = 1";
+            let rendered = render(source, |source| {
+                parse(source).unwrap_err().diagnostics().remove(0)
+            });
+
+            assert_eq!(
+                rendered,
+                "error: expected name, found `é`\n \
+                 --> src/main.firework:1:5\n  \
+                   |\n\
+                 1 | let é: i64 = 1\n  \
+                   |     ^ expected name\n"
+            );
+        }
+
+        #[test]
+        fn renders_multi_line_spans_with_notes() {
+            let source = "let main: i64 = do {\n  puts(\"a\");\n  1\n}\n";
+            let rendered = render(source, |_| {
+                Diagnostic::warning("unused block")
+                    .with_span(Span {
+                        file: 0,
+                        start: 16,
+                        end: 39,
+                        line: 1,
+                        column: 17,
+                    })
+                    .with_label("this block")
+                    .with_note("blocks are expressions")
+                    .with_help("remove it")
+            });
+
+            assert_eq!(
+                rendered,
+                "warning: unused block\n \
+                 --> src/main.firework:1:17\n  \
+                   |\n\
+                 1 |   let main: i64 = do {\n  \
+                   |  _________________^\n\
+                 2 | |   puts(\"a\");\n\
+                 3 | |   1\n\
+                 4 | | }\n  \
+                   | |_^ this block\n  \
+                   |\n  \
+                   = note: blocks are expressions\n  \
+                   = help: remove it\n"
+            );
+        }
+
+        #[test]
+        fn describes_diagnostics_as_json() {
+            let source = "let x: i64 = 1\nlet y: é = x";
+            let mut sources = SourceMap::default();
+            sources.add(PathBuf::from("src/main.firework"), source.to_string());
+
+            let span = Span {
+                file: 0,
+                start: 22,
+                end: 24,
+                line: 2,
+                column: 8,
+            };
+            let diagnostic = parse(source).unwrap_err().diagnostics().remove(0);
+            let diagnostic = diagnostic.with_note("types are ASCII").with_suggestion(
+                "use an integer type",
+                span,
+                "i64",
+            );
+
+            assert_eq!(
+                diagnostic.to_json(&sources),
+                json!({
+                    "severity": "error",
+                    "code": "syntax-error",
+                    "message": "expected type, found `é`",
+                    "file": "src/main.firework",
+                    "span": {
+                        "byte_start": 22,
+                        "byte_end": 24,
+                        "line_start": 2,
+                        "column_start": 8,
+                        "line_end": 2,
+                        "column_end": 9,
+                    },
+                    "label": "expected type",
+                    "notes": ["types are ASCII"],
+                    "suggestions": [{
+                        "message": "use an integer type",
+                        "span": {
+                            "byte_start": 22,
+                            "byte_end": 24,
+                            "line_start": 2,
+                            "column_start": 8,
+                            "line_end": 2,
+                            "column_end": 9,
+                        },
+                        "replacement": "i64",
+                    }],
+                })
+            );
+        }
+    }
+    mod modules_tests {
+        use crate::modules::ModuleScope;
+        use crate::parser::{parse_file, SourceModule};
+        use std::path::PathBuf;
+
+        fn module(id: usize, name: &str, source: &str) -> SourceModule {
+            SourceModule {
+                id,
+                name: name.to_string(),
+                path: PathBuf::from(format!("src/{}.firework", name)),
+                source: source.to_string(),
+                ast: parse_file(source, id).unwrap(),
+            }
+        }
+
+        #[test]
+        fn mangles_the_functions_of_imported_modules() {
+            let math = module(
+                0,
+                "math",
+                "let add(a: i64 b: i64): i64 = a\nexport let c_add(a: i64): i64 = a\n",
+            );
+            let main = module(1, "main", "import math\nlet f(x: i64): i64 = x\n");
+            let modules = [math, main];
+            let scope = ModuleScope::new(&modules[1], &modules).unwrap();
+
+            assert_eq!(scope.resolve("add").unwrap(), "math::add");
+            assert_eq!(scope.resolve("math::add").unwrap(), "math::add");
+            // Exported functions are called from C by their name
+            assert_eq!(scope.resolve("c_add").unwrap(), "c_add");
+            assert_eq!(scope.resolve("f").unwrap(), "f");
+            // Names that aren't defined may refer to the core
+            assert_eq!(scope.resolve("puts").unwrap(), "puts");
+
+            let scope = ModuleScope::new(&modules[0], &modules).unwrap();
+
+            assert_eq!(scope.definition_symbol("add"), "math::add");
+            assert_eq!(scope.definition_symbol("c_add"), "c_add");
+        }
+
+        #[test]
+        fn qualifies_names_with_the_alias_of_an_import() {
+            let math = module(0, "math", "let add(a: i64 b: i64): i64 = a\n");
+            let main = module(1, "main", "import math as m\nlet main: Unit = ()\n");
+            let modules = [math, main];
+            let scope = ModuleScope::new(&modules[1], &modules).unwrap();
+
+            assert_eq!(scope.resolve("m::add").unwrap(), "math::add");
+            // Imports with an alias don't bring unqualified names into scope
+            assert_eq!(scope.resolve("add").unwrap(), "add");
+        }
+
+        #[test]
+        fn rejects_private_names() {
+            let math = module(
+                0,
+                "math",
+                "module math exports (add)\n\
+                 let add(a: i64 b: i64): i64 = helper(a)\n\
+                 let helper(a: i64): i64 = a\n",
+            );
+            let listed = module(1, "main", "import math (helper)\nlet main: Unit = ()\n");
+            let modules = [math, listed];
+            let error = ModuleScope::new(&modules[1], &modules).unwrap_err();
+
+            assert_eq!(error.to_string(), "`helper` is private to module `math`");
+
+            let [math, _] = modules;
+            let qualified = module(1, "main", "import math\nlet main: Unit = ()\n");
+            let modules = [math, qualified];
+            let scope = ModuleScope::new(&modules[1], &modules).unwrap();
+
+            assert_eq!(
+                scope.resolve("math::helper").unwrap_err().message,
+                "`math::helper` is private to its module"
+            );
+        }
+
+        #[test]
+        fn reports_ambiguous_imports() {
+            let a = module(0, "a", "let add(x: i64): i64 = x\n");
+            let b = module(1, "b", "let add(x: i64): i64 = x\n");
+            let main = module(2, "main", "import a\nimport b\nlet main: Unit = ()\n");
+            let modules = [a, b, main];
+            let scope = ModuleScope::new(&modules[2], &modules).unwrap();
+
+            assert_eq!(
+                scope.resolve("add").unwrap_err().message,
+                "`add` is ambiguous, it could refer to any of a::add, b::add"
+            );
+            assert_eq!(scope.resolve("b::add").unwrap(), "b::add");
+        }
+    }
+    mod codegen_tests {
+        use crate::codegen::{CodeGen, OptLevel, TargetOptions};
+        use crate::error::FireworkError;
+        use crate::parser::{parse_file, SourceModule};
+        use inkwell_llvm12::context::Context;
+        use inkwell_llvm12::OptimizationLevel;
+        use std::path::PathBuf;
+
+        /// Compiles a program made of a main module with an empty core, then inspects it
+        fn compile<T>(
+            source: &str,
+            configure: impl FnOnce(&mut CodeGen),
+            inspect: impl FnOnce(&CodeGen, &[SourceModule]) -> T,
+        ) -> T {
+            try_compile(source, configure, inspect).unwrap()
+        }
+
+        fn try_compile<T>(
+            source: &str,
+            configure: impl FnOnce(&mut CodeGen),
+            inspect: impl FnOnce(&CodeGen, &[SourceModule]) -> T,
+        ) -> Result<T, FireworkError> {
+            let context = Context::create();
+            let module = context.create_module("main");
+            let execution_engine = module
+                .create_jit_execution_engine(OptimizationLevel::None)
+                .unwrap();
+            let mut codegen =
+                CodeGen::new(&context, module, context.create_builder(), execution_engine);
+            codegen.set_core_source(String::new());
+            configure(&mut codegen);
+
+            let modules = vec![SourceModule {
+                id: 0,
+                name: "main".to_string(),
+                path: PathBuf::from("src/main.firework"),
+                source: source.to_string(),
+                ast: parse_file(source, 0).unwrap(),
+            }];
+            codegen.compile(modules.clone())?;
+
+            Ok(inspect(&codegen, &modules))
+        }
+
+        fn ir(source: &str) -> String {
+            compile(source, |_| (), |codegen, _| codegen.ir())
+        }
+
+        fn compile_error(source: &str) -> FireworkError {
+            try_compile(source, |_| (), |_, _| ()).unwrap_err()
+        }
+
+        /// Returns the definition of the function whose ```define``` line contains
+        /// ```signature```
+        fn definition<'a>(ir: &'a str, signature: &str) -> &'a str {
+            let start = ir
+                .match_indices("define ")
+                .map(|(start, _)| start)
+                .find(|&start| ir[start..].lines().next().unwrap().contains(signature))
+                .unwrap();
+
+            &ir[start..start + ir[start..].find("\n}\n").unwrap()]
+        }
+
+        fn target(triple: &str) -> impl FnOnce(&mut CodeGen) + '_ {
+            move |codegen| {
+                codegen
+                    .set_target(TargetOptions {
+                        triple: Some(triple.to_string()),
+                        ..TargetOptions::default()
+                    })
+                    .unwrap()
+            }
+        }
+
+        const COUNT: &str = "let count(n: i64): i64 = if ==(n, 0) do 0 else count(-(n, 1))\n\
+                             let main: i64 = count(3)\n";
+
+        #[test]
+        fn compiles_tail_calls_with_tailcc() {
+            let (ir, warnings) = compile(
+                COUNT,
+                |_| (),
+                |codegen, _| (codegen.ir(), codegen.take_diagnostics()),
+            );
+
+            assert!(warnings.is_empty());
+            assert!(ir.contains("define tailcc i64 @count(i64 %0)"));
+            assert!(ir.contains("tail call tailcc i64 @count("));
+            // main is called from C
+            assert!(ir.contains("define i32 @main()"));
+        }
+
+        #[test]
+        fn extends_narrow_exit_codes() {
+            let ir = ir("let f(a: u8): u8 = a\nlet main: i64 = f(200)\n");
+
+            assert!(definition(&ir, "@main(").contains("zext i8 %f to i32"));
+        }
+
+        #[test]
+        fn compares_unsigned_integers_as_unsigned() {
+            let ir = ir("let lt(a: i64 b: i64): bool = <(a, b)\n\
+                         let ult(a: u64 b: u64): bool = <(a, b)\n\
+                         let ge(a: u8 b: u8): bool = >=(a, b)\n\
+                         let eq(a: i32): bool = ==(a, 1)\n\
+                         let main: Unit = ()\n");
+
+            assert!(definition(&ir, "@lt(").contains("icmp slt i64 %0, %1"));
+            assert!(definition(&ir, "@ult(").contains("icmp ult i64 %0, %1"));
+            assert!(definition(&ir, "@ge(").contains("icmp uge i8 %0, %1"));
+            assert!(definition(&ir, "@eq(").contains("icmp eq i32 %0, 1"));
+        }
+
+        #[test]
+        fn short_circuits_boolean_operators() {
+            let ir = ir("let both(a: bool b: bool): bool = &&(a, b)\n\
+                         let either(a: bool b: bool): bool = ||(a, b)\n\
+                         let main: Unit = ()\n");
+            let both = definition(&ir, "@both(");
+            let either = definition(&ir, "@either(");
+
+            // The second operand is only evaluated in the rhs block
+            assert!(both.contains("br i1 %ifcond, label %rhs, label %logicalcont"));
+            assert!(either.contains("br i1 %ifcond, label %logicalcont, label %rhs"));
+            assert!(both.contains("phi i1 [ %ifcond, %entry ], [ %ifcond1, %rhs ]"));
+        }
+
+        #[test]
+        fn compiles_bitwise_and_shift_operators() {
+            let ir = ir("let flip(a: i64): i64 = ~(^(a, |(a, &(a, 255))))\n\
+                         let shl(a: i64 b: i64): i64 = <<(a, b)\n\
+                         let sar(a: i64 b: i64): i64 = >>(a, b)\n\
+                         let shr(a: u64 b: u64): u64 = >>(a, b)\n\
+                         let main: Unit = ()\n");
+
+            assert!(definition(&ir, "@\"&\"(").contains("and i64 %0, %1"));
+            assert!(definition(&ir, "@\"|\"(").contains("or i64 %0, %1"));
+            assert!(definition(&ir, "@\"^\"(").contains("xor i64 %0, %1"));
+            assert!(definition(&ir, "@\"~\"(").contains("xor i64 %0, -1"));
+            assert!(definition(&ir, "@flip(").contains("call i64 @\"~\"(i64 %\"^\")"));
+            assert!(definition(&ir, "@shl(").contains("icmp uge i64 %1, 64"));
+            assert!(definition(&ir, "@shl(").contains("shl i64 %0, %1"));
+            assert!(definition(&ir, "@sar(").contains("ashr i64 %0, %1"));
+            assert!(definition(&ir, "@shr(").contains("lshr i64 %0, %1"));
+        }
+
+        #[test]
+        fn compiles_mutually_recursive_functions_in_any_order() {
+            let source = "let is_even(n: i64): bool = if ==(n, 0) do true else is_odd(-(n, 1))\n\
+                          let is_odd(n: i64): bool = if ==(n, 0) do false else is_even(-(n, 1))\n\
+                          let main: i64 = if is_even(7) do 0 else 3\n";
+            let (ir, warnings, exit_code) = compile(
+                source,
+                |_| (),
+                |codegen, _| {
+                    let exit_code = unsafe { codegen.call_main() };
+                    (codegen.ir(), codegen.take_diagnostics(), exit_code)
+                },
+            );
+
+            assert!(ir.contains("tail call tailcc i1 @is_odd("));
+            assert!(ir.contains("tail call tailcc i1 @is_even("));
+            assert!(warnings.is_empty());
+            assert_eq!(exit_code, 3);
+        }
+
+        #[test]
+        fn traps_on_overflow_unless_disabled() {
+            let source = "let add(a: i64 b: i64): i64 = +(a, b)\nlet main: i64 = add(1, 2)\n";
+            let checked = ir(source);
+            let unchecked = compile(
+                source,
+                |codegen| codegen.set_overflow_checks(false),
+                |codegen, _| codegen.ir(),
+            );
+
+            assert!(checked.contains("@llvm.sadd.with.overflow.i64(i64 %0, i64 %1)"));
+            assert!(checked.contains("attempt to add with overflow"));
+            assert!(checked.contains("call void @firework_panic("));
+            assert!(!unchecked.contains("with.overflow"));
+        }
+
+        #[test]
+        fn checks_overflow_at_the_width_of_the_operands() {
+            let ir = ir("let add(a: i32 b: i32): i32 = +(a, b)\n\
+                         let sub(a: u64): u64 = -(a, 1)\n\
+                         let div(a: u8 b: u8): u8 = /(a, b)\n\
+                         let shift(a: i16 b: i16): i16 = >>(a, b)\n\
+                         let main: Unit = ()\n");
+
+            assert!(ir.contains("@llvm.sadd.with.overflow.i32(i32 %0, i32 %1)"));
+            assert!(ir.contains("@llvm.usub.with.overflow.i64(i64 %0, i64 1)"));
+            assert!(definition(&ir, "@div(").contains("udiv i8 %0, %1"));
+            assert!(!definition(&ir, "@div(").contains("ismin"));
+            assert!(definition(&ir, "@shift(").contains("icmp uge i16 %1, 16"));
+            assert!(definition(&ir, "@shift(").contains("ashr i16 %0, %1"));
+        }
+
+        #[test]
+        fn folds_constants() {
+            let ir = ir("let answer: i64 = *(6, 7)\nlet main: i64 = answer\n");
+
+            assert!(ir.contains("@answer = constant i64 42"));
+        }
+
+        #[test]
+        fn folds_constants_to_their_declared_type() {
+            let ir = ir("let small: i32 = -(5, 2)\nlet byte: u8 = 255\nlet main: i64 = small\n");
+
+            assert!(ir.contains("@small = constant i32 3"));
+            assert!(ir.contains("@byte = constant i8 -1"));
+
+            let error = compile_error("let big: i8 = 256\nlet main: Unit = ()\n");
+
+            assert_eq!(
+                error.to_string(),
+                "The value of constant `big` doesn't fit in 8 bits"
+            );
+        }
+
+        #[test]
+        fn panics_with_the_location_of_the_call() {
+            let ir = ir(
+                "let check(n: i64): Unit = assert(>(n, 0), \"n must be positive\")\n\
+                 let fail(n: i64): Unit = panic(\"failed\")\n\
+                 let main: Unit = ()\n",
+            );
+
+            assert!(ir.contains("c\"n must be positive\\00\""));
+            assert!(ir.contains("c\"failed\\00\""));
+            assert!(ir.contains("c\"src/main.firework\\00\""));
+            // The message is followed by the file, the line and the column of the call
+            assert!(definition(&ir, "@check(").contains("call void @firework_panic("));
+            assert!(definition(&ir, "@check(").contains(", i64 1, i64 27)"));
+            assert!(definition(&ir, "@fail(").contains(", i64 2, i64 26)"));
+
+            let panic_function = definition(&ir, "@firework_panic(");
+
+            assert!(ir.contains("c\"panicked at %s:%lld:%lld: %s\\0A\\00\""));
+            assert!(panic_function.contains("call void @exit(i32 101)"));
+        }
+
+        #[test]
+        fn compiles_option_as_a_tagged_struct() {
+            let ir = ir(
+                "let unwrap(o: Option i64): i64 = match o { Some(x) -> x, None -> 0 }\n\
+                         let main: i64 = unwrap(Some(1))\n",
+            );
+
+            assert!(ir.contains("%\"Option i64\" = type { i1, i64 }"));
+            assert!(ir.contains("define tailcc i64 @unwrap(%\"Option i64\" %0)"));
+        }
+
+        #[test]
+        fn extends_small_integers_of_extern_functions() {
+            let ir = ir("extern \"C\" let next(c: u8): u8\n\
+                         extern \"C\" let widen(c: i8): i16\n\
+                         let f(c: u8): i64 = next(c)\n\
+                         let g(c: i8): i64 = widen(c)\n\
+                         let h(c: u8): i64 = +(c, 1)\n\
+                         let main: i64 = f(200)\n");
+
+            assert!(ir.contains("declare zeroext i8 @next(i8 zeroext)"));
+            assert!(ir.contains("declare signext i16 @widen(i8 signext)"));
+            assert!(ir.contains("zext i8 %next to i64"));
+            assert!(ir.contains("sext i16 %widen to i64"));
+            assert!(ir.contains("zext i8 %result to i64"));
+        }
+
+        #[test]
+        fn shadows_option_helpers_with_functions_of_the_program() {
+            let ir = ir("let map(x: i64): i64 = +(x, 1)\n\
+                         let f(o: Option i64): i64 = unwrap_or(o, map(1))\n\
+                         let main: i64 = f(None)\n");
+
+            assert!(ir.contains("define tailcc i64 @map(i64 %0)"));
+            assert!(ir.contains("call tailcc i64 @map(i64 1)"));
+            assert!(!ir.contains("@unwrap_or"));
+        }
+
+        #[test]
+        fn exports_functions_with_the_c_calling_convention() {
+            let source = "export let add(a: i32 b: i32): i32 = a\nlet main: Unit = ()\n";
+            let (ir, header) = compile(
+                source,
+                |_| (),
+                |codegen, modules| (codegen.ir(), codegen.c_header(modules, "ADD_H")),
+            );
+
+            assert!(ir.contains("define i32 @add(i32"));
+            assert!(header.contains("#ifndef ADD_H"));
+            assert!(header.contains("int32_t add(int32_t a, int32_t b);"));
+        }
+
+        #[test]
+        fn converts_values_to_the_return_type() {
+            let ir = ir("export let add(a: i32 b: i32): i32 = +(a, b)\nlet main: Unit = ()\n");
+            let add = definition(&ir, "@add(");
+
+            assert!(add.contains("ret i32"));
+            assert!(!add.contains("ret i64"));
+
+            let error = compile_error("let f(a: i64): str = a\nlet main: Unit = ()\n");
+
+            assert_eq!(
+                error.to_string(),
+                "Mismatched types, `f` returns `str` but its value is of type `i64`"
+            );
+        }
+
+        #[test]
+        fn checks_the_arguments_of_calls() {
+            let error = compile_error("let f: bool = bool_eq(1, 2)\nlet main: Unit = ()\n");
+
+            assert_eq!(
+                error.to_string(),
+                "Mismatched types, argument 1 of `bool_eq` is of type `i64` instead of `bool`"
+            );
+
+            let error = compile_error("let f(a: i64): i64 = a\nlet main: i64 = f(1, 2)\n");
+
+            assert_eq!(error.to_string(), "`f` takes 1 argument but 2 were given");
+        }
+
+        #[test]
+        fn optimizes_at_the_requested_level() {
+            let ir = compile(
+                COUNT,
+                |codegen| codegen.set_opt_level(OptLevel::O2),
+                |codegen, _| {
+                    codegen.optimize();
+                    codegen.ir()
+                },
+            );
+
+            assert!(!ir.contains("call tailcc i64 @count("));
+            assert!(ir.contains("ret i32 0"));
+        }
+
+        #[test]
+        fn extends_small_integers_of_exported_functions() {
+            let source = "export let f(a: bool b: u16 c: i8 d: u32): bool = a\n\
+                          let main: Unit = ()\n";
+            let host = ir(source);
+            let riscv = compile(source, target("riscv64-unknown-linux-gnu"), |codegen, _| {
+                codegen.ir()
+            });
+
+            assert!(host.contains(
+                "define zeroext i1 @f(i1 zeroext %0, i16 zeroext %1, i8 signext %2, i32 %3)"
+            ));
+            // The RISC-V ABIs sign extend 32-bit integers even if they're unsigned
+            assert!(riscv.contains(
+                "define zeroext i1 @f(i1 zeroext %0, i16 zeroext %1, i8 signext %2, i32 signext %3)"
+            ));
+        }
+
+        /// Returns the line of the debug location attached to the instruction containing
+        /// ```instruction```
+        fn debug_line(ir: &str, instruction: &str) -> usize {
+            let location = ir
+                .lines()
+                .find(|line| line.contains(instruction))
+                .and_then(|line| line.rsplit("!dbg ").next())
+                .unwrap();
+            let definition = format!("{} = !DILocation(line: ", location);
+            let line = ir
+                .lines()
+                .find_map(|line| line.strip_prefix(&definition))
+                .unwrap();
+
+            line[..line.find(',').unwrap()].parse().unwrap()
+        }
+
+        #[test]
+        fn locates_every_statement_for_debuggers() {
+            let source = "let f(a: i64 o: Option i64): i64 = do {\n  let b: i64 = +(a, 1);\n  \
+                          match o {\n    Some(x) -> x,\n    None -> 0\n  }\n}\n\
+                          let main: i64 = f(1, None)\n";
+            let ir = compile(
+                source,
+                |codegen| {
+                    codegen.set_target(TargetOptions::default()).unwrap();
+                    codegen.set_debug_info(true);
+                },
+                |codegen, _| codegen.ir(),
+            );
+
+            assert_eq!(
+                debug_line(&ir, "@llvm.sadd.with.overflow.i64(i64 %0, i64 1)"),
+                2
+            );
+            assert_eq!(debug_line(&ir, "%is_present = extractvalue"), 3);
+            assert_eq!(debug_line(&ir, "ret i64 %x,"), 4);
+            assert_eq!(debug_line(&ir, "ret i64 0"), 5);
+            assert_eq!(debug_line(&ir, "call tailcc i64 @f(i64 1"), 8);
+        }
+
+        #[test]
+        fn warns_about_recursive_calls_not_in_tail_position() {
+            let source = "let fact(n: i64): i64 = if ==(n, 0) do 1 else *(n, fact(-(n, 1)))\n\
+                          let main: i64 = fact(3)\n";
+            let warnings = compile(source, |_| (), |codegen, _| codegen.take_diagnostics());
+
+            assert_eq!(warnings.len(), 1);
+            assert_eq!(warnings[0].code, Some("non-tail-recursion"));
+            assert_eq!(
+                warnings[0].message,
+                "Recursive call to `fact` in `fact` is not in tail position and may overflow the stack"
+            );
+            assert_eq!(warnings[0].label.as_deref(), Some("not in tail position"));
+
+            let span = warnings[0].span.unwrap();
+            assert_eq!(&source[span.start..span.end], "fact(-(n, 1))");
+        }
+
+        #[test]
+        fn falls_back_to_fastcc_without_tailcc() {
+            let (ir, warnings) =
+                compile(COUNT, target("riscv64-unknown-linux-gnu"), |codegen, _| {
+                    (codegen.ir(), codegen.take_diagnostics())
+                });
+
+            assert!(ir.contains("target triple = \"riscv64-unknown-linux-gnu\""));
+            assert!(ir.contains("define fastcc i64 @count(i64 %0)"));
+            // The tail call isn't guaranteed without tailcc
+            assert_eq!(warnings.len(), 1);
+            assert_eq!(warnings[0].code, Some("non-tail-recursion"));
+            assert!(warnings[0].notes[0].contains("riscv64 target doesn't support"));
+        }
+
+        #[test]
+        fn adds_an_entry_point_to_webassembly_modules() {
+            let ir = compile(COUNT, target("wasm32-unknown-wasi"), |codegen, _| {
+                codegen.ir()
+            });
+
+            assert!(ir.contains("define void @_start()"));
+            assert!(ir.contains("\"wasm-export-name\"=\"_start\""));
+        }
+
+        #[test]
+        fn declares_functions_of_the_wasi_runtime_again() {
+            let ir = compile(
+                "extern \"C\" let exit(code: i32): Unit\nlet main: Unit = exit(3)\n",
+                target("wasm32-unknown-wasi"),
+                |codegen, _| codegen.ir(),
+            );
+
+            assert!(definition(&ir, "@main(").contains("call void @exit(i32 3)"));
+
+            let error = try_compile(
+                "extern \"C\" let exit(code: i64): Unit\nlet main: Unit = exit(3)\n",
+                target("wasm32-unknown-wasi"),
+                |_, _| (),
+            )
+            .unwrap_err();
+
+            assert_eq!(
+                error.to_string(),
+                "`exit` is already declared with a different type"
+            );
+        }
+    }
+    mod project_tests {
+        use crate::codegen::CodeGen;
+        use crate::error::FireworkError;
+        use crate::firework_project::{emit_requests, Emit, FireworkProject, Output};
+        use inkwell_llvm12::context::Context;
+        use inkwell_llvm12::OptimizationLevel;
+        use std::fs;
+        use std::path::PathBuf;
+
+        /// Writes the modules of a project to a temporary directory and loads them, returns the
+        /// names of the loaded modules in the order they're compiled
+        fn load(project: &str, modules: &[(&str, &str)]) -> Result<Vec<String>, FireworkError> {
+            let root =
+                std::env::temp_dir().join(format!("firework_{}_{}", project, std::process::id()));
+
+            for (path, source) in modules {
+                let path = root.join("src").join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, source).unwrap();
+            }
+
+            let context = Context::create();
+            let module = context.create_module("main");
+            let execution_engine = module
+                .create_jit_execution_engine(OptimizationLevel::None)
+                .unwrap();
+            let project = FireworkProject::new(CodeGen::new(
+                &context,
+                module,
+                context.create_builder(),
+                execution_engine,
+            ));
+
+            let loaded = project.load_modules_from(&root);
+            fs::remove_dir_all(&root).unwrap();
+
+            Ok(loaded?.into_iter().map(|module| module.name).collect())
+        }
+
+        #[test]
+        fn reads_emit_requests() {
+            assert_eq!(
+                emit_requests(&[], None).unwrap(),
+                [(Emit::Exe, Output::Default)]
+            );
+            assert_eq!(
+                emit_requests(&["ir=-", "obj=out/main.o", "tokens"], None).unwrap(),
+                [
+                    (Emit::Ir, Output::Stdout),
+                    (Emit::Object, Output::File(PathBuf::from("out/main.o"))),
+                    (Emit::Tokens, Output::Default),
+                ]
+            );
+            assert_eq!(
+                emit_requests(&["asm"], Some("main.s")).unwrap(),
+                [(Emit::Asm, Output::File(PathBuf::from("main.s")))]
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_emit_requests() {
+            assert_eq!(
+                emit_requests(&["wasm"], None).unwrap_err(),
+                "Unknown artifact `wasm`, expected one of ast, tokens, ir, bc, asm, obj, exe"
+            );
+            assert_eq!(
+                emit_requests(&["ir=main.ll"], Some("other.ll")).unwrap_err(),
+                "-o conflicts with the path given to --emit"
+            );
+            assert!(emit_requests(&["ir", "asm"], Some("out"))
+                .unwrap_err()
+                .starts_with("-o can only be used when emitting a single artifact"));
+        }
+
+        #[test]
+        fn loads_imported_modules_once_before_their_importers() {
+            let modules = load(
+                "imports",
+                &[
+                    (
+                        "main.firework",
+                        "import a\nimport utils::math\nlet main: Unit = ()\n",
+                    ),
+                    ("a.firework", "import utils::math\nlet f(x: i64): i64 = x\n"),
+                    ("utils/math.firework", "let g(x: i64): i64 = x\n"),
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(modules, ["utils::math", "a", "main"]);
+        }
+
+        #[test]
+        fn reports_import_cycles() {
+            let error = load(
+                "cycle",
+                &[
+                    ("main.firework", "import a\nlet main: Unit = ()\n"),
+                    ("a.firework", "import b\nlet f(x: i64): i64 = x\n"),
+                    ("b.firework", "import a\nlet g(x: i64): i64 = x\n"),
+                ],
+            )
+            .unwrap_err();
+
+            assert_eq!(error.to_string(), "Import cycle detected: a -> b -> a");
+        }
+
+        #[test]
+        fn reports_missing_modules() {
+            let error = load(
+                "missing",
+                &[("main.firework", "import missing\nlet main: Unit = ()\n")],
+            )
+            .unwrap_err();
+
+            assert!(error
+                .to_string()
+                .starts_with("Couldn't find module `missing`, expected it at "));
+            // The error points at the import
+            let span = error.diagnostics()[0].span.unwrap();
+            assert_eq!((span.start, span.end), (0, 14));
+        }
+    }
 }
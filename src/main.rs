@@ -1,19 +1,22 @@
 extern crate inkwell_llvm12 as inkwell;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use firework_lang::codegen::CodeGen;
+use firework_lang::codegen::{CodeGen, IrStream, OptLevel, TargetOptions};
 use firework_lang::core::install_core;
-use firework_lang::firework_project::FireworkProject;
-use firework_lang::{todo_feature, unrecoverable_error};
+use firework_lang::diagnostics::{Diagnostic, ErrorFormat, SourceMap};
+use firework_lang::error::FireworkError;
+use firework_lang::firework_project::{emit_requests, Emit, FireworkProject, LibraryKind, Output};
 use inkwell::context::Context;
-use inkwell::OptimizationLevel;
-use std::error::Error;
+use std::path::Path;
+use std::process;
 use strsim::damerau_levenshtein;
 
-const SUBCOMMANDS: [&str; 6] = ["install", "new", "build", "dump_ir", "dump_ast", "repl"];
+const SUBCOMMANDS: [&str; 8] = [
+    "install", "new", "build", "dump_ir", "dump_asm", "dump_ast", "header", "repl",
+];
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
     let clap_app = App::new("Firework")
         .settings(&[
             AppSettings::ColorAlways,
@@ -23,41 +26,240 @@ fn main() -> Result<(), Box<dyn Error>> {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::with_name("release")
+                .long("release")
+                .global(true)
+                .help("Disables the runtime checks for integer overflow and division by zero"),
+        )
+        .arg(
+            Arg::with_name("opt_level")
+                .short("O")
+                .takes_value(true)
+                .global(true)
+                .value_name("level")
+                .possible_values(&["0", "1", "2", "3", "s"])
+                .help("Optimization level of the JIT and the built artifacts, defaults to 0"),
+        )
+        .arg(
+            Arg::with_name("error_format")
+                .long("error-format")
+                .takes_value(true)
+                .global(true)
+                .value_name("format")
+                .possible_values(&["human", "json"])
+                .help("Prints errors and warnings as text, or as one JSON object per line"),
+        )
+        .arg(
+            Arg::with_name("print_after_passes")
+                .long("print-after-passes")
+                .global(true)
+                .help(
+                    "Prints the IR to stderr once the optimization passes ran, or to stdout with \
+                     --error-format=json",
+                ),
+        )
         .subcommand(
             SubCommand::with_name("install")
                 .help("Installs or updates the core library required of Firework"),
         )
         .subcommand(SubCommand::with_name("new").arg(Arg::with_name("project").takes_value(true)))
+        .subcommand(
+            SubCommand::with_name("build")
+                .help("Builds a firework project into an executable, or a library")
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("debug_info")
+                        .short("g")
+                        .help("Emits DWARF debug information, for debuggers like gdb and lldb"),
+                )
+                .arg(
+                    Arg::with_name("lib")
+                        .long("lib")
+                        .help("Builds a static library, target/lib<project>.a"),
+                )
+                .arg(
+                    Arg::with_name("dylib")
+                        .long("dylib")
+                        .conflicts_with("lib")
+                        .help("Builds a shared library, target/lib<project>.so"),
+                )
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .value_name("kind[=path]")
+                        .help(
+                            "Artifacts to write, any of ast, tokens, ir, bc, asm, obj and exe, \
+                             defaults to exe. Each one is written to target/ unless a path is \
+                             given, - is stdout",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .value_name("path")
+                        .help("Path of the only artifact, - is stdout"),
+                ),
+        )
         .subcommand(SubCommand::with_name("run").help("Runs a firework project"))
-        .subcommand(SubCommand::with_name("dump_ir").help("Dumps LLVM's output to ir.ll"))
-        .subcommand(SubCommand::with_name("dump_asm").help("Dumps LLVM's assembly output to ir.ll"))
+        .subcommand(
+            SubCommand::with_name("dump_ir")
+                .help("Dumps LLVM's output to ir.ll")
+                .args(&target_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("dump_asm")
+                .help("Dumps LLVM's assembly output to out.asm")
+                .args(&target_args()),
+        )
+        .subcommand(SubCommand::with_name("dump_ast").help("Prints the AST of every module"))
+        .subcommand(
+            SubCommand::with_name("header")
+                .help("Writes a C header declaring the project's exported functions")
+                .arg(
+                    Arg::with_name("output")
+                        .takes_value(true)
+                        .help("Path of the header, defaults to target/<project>.h"),
+                ),
+        )
         .subcommand(SubCommand::with_name("repl").help("Runs the firework repl"));
 
     let matches = clap_app.get_matches();
+    let format = match matches.value_of("error_format") {
+        Some("json") => ErrorFormat::Json,
+        _ => ErrorFormat::Human,
+    };
+    let opt_level = matches
+        .value_of("opt_level")
+        .map_or(Ok(OptLevel::default()), str::parse)
+        .unwrap_or_else(|err| {
+            exit_with_errors(vec![Diagnostic::error(err)], &SourceMap::default(), format)
+        });
+
     let context = Context::create();
     let module = context.create_module("main");
     let execution_engine = module
-        .create_jit_execution_engine(OptimizationLevel::Aggressive)
+        .create_jit_execution_engine(opt_level.into())
         .unwrap();
 
-    let codegen = CodeGen::new(&context, module, context.create_builder(), execution_engine);
+    let mut codegen = CodeGen::new(&context, module, context.create_builder(), execution_engine);
+    codegen.set_overflow_checks(!matches.is_present("release"));
+    codegen.set_opt_level(opt_level);
+    // stderr only holds JSON objects with --error-format=json
+    let ir_stream = match format {
+        ErrorFormat::Human => IrStream::Stderr,
+        ErrorFormat::Json => IrStream::Stdout,
+    };
+    codegen.set_print_after_passes(if matches.is_present("print_after_passes") {
+        Some(ir_stream)
+    } else {
+        None
+    });
+
+    if let (_, Some(matches)) = matches.subcommand() {
+        codegen
+            .set_target(TargetOptions {
+                triple: matches.value_of("target").map(String::from),
+                cpu: matches.value_of("cpu").map(String::from),
+                features: matches.value_of("features").map(String::from),
+            })
+            .unwrap_or_else(|err| {
+                exit_with_errors(err.diagnostics(), &SourceMap::default(), format)
+            });
+        codegen.set_debug_info(matches.is_present("debug_info"));
+    }
+
     let project = FireworkProject::new(codegen);
+    let result = run_subcommand(&matches, &project, format);
 
+    emit_warnings(&project, format);
+
+    match result {
+        Ok(0) => (),
+        // The exit code of the project's main when it was run
+        Ok(exit_code) => process::exit(exit_code),
+        Err(err) => exit_with_errors(err.diagnostics(), &project.sources(), format),
+    }
+}
+
+/// Runs the subcommand, returns the exit code of the project if it was run
+fn run_subcommand(
+    matches: &ArgMatches,
+    project: &FireworkProject,
+    format: ErrorFormat,
+) -> Result<i32, FireworkError> {
     match matches.subcommand() {
         ("new", Some(matches)) => {
             if let Some(project_name) = matches.value_of("project") {
-                project.new_project(project_name)
+                project.new_project(project_name)?
+            } else {
+                exit_with_errors(
+                    vec![Diagnostic::error("No project name supplied!")],
+                    &SourceMap::default(),
+                    format,
+                )
+            }
+        }
+        ("run", _) => {
+            project.compile()?;
+
+            // Reported before the program's own output
+            emit_warnings(project, format);
+
+            return project.run();
+        }
+        ("build", Some(matches)) => {
+            let library = if matches.is_present("lib") {
+                Some(LibraryKind::Static)
+            } else if matches.is_present("dylib") {
+                Some(LibraryKind::Dynamic)
             } else {
-                unrecoverable_error!("No project name supplied!")
+                None
+            };
+
+            let emit = matches
+                .values_of("emit")
+                .map(Iterator::collect)
+                .unwrap_or_else(Vec::new);
+            let requests = emit_requests(&emit, matches.value_of("output")).unwrap_or_else(|err| {
+                exit_with_errors(vec![Diagnostic::error(err)], &SourceMap::default(), format)
+            });
+
+            // Reported on stderr so that artifacts written to stdout can be piped, stderr only
+            // holds JSON objects with --error-format=json
+            for path in project.emit(&requests, library)? {
+                if format == ErrorFormat::Human {
+                    eprintln!("Wrote {}", path.display());
+                }
             }
         }
-        ("run", _) => project.run()?,
-        ("dump_ir", _) => project.dump_ir()?,
-        ("dump_asm", _) => project.dump_asm()?,
-        ("repl", _) => todo_feature!("The REPL"),
+        ("dump_ir", _) => {
+            project.emit(&[(Emit::Ir, Output::from("ir.ll"))], None)?;
+        }
+        ("dump_asm", _) => {
+            project.emit(&[(Emit::Asm, Output::from("out.asm"))], None)?;
+        }
+        ("dump_ast", _) => {
+            project.emit(&[(Emit::Ast, Output::Stdout)], None)?;
+        }
+        ("header", Some(matches)) => {
+            let path = project.header(matches.value_of("output").map(Path::new))?;
+            println!("Wrote {}", path.display());
+        }
+        ("repl", _) => exit_with_errors(
+            vec![Diagnostic::error("The REPL is not implemented yet")],
+            &SourceMap::default(),
+            format,
+        ),
         ("install", _) => install_core()?,
         (other, _) => {
-            if !other.chars().map(|c| c.is_numeric()).all(|x| x) {
+            if !other.chars().all(|c| c.is_numeric()) {
                 let words: Vec<usize> = SUBCOMMANDS
                     .iter()
                     .map(|command| damerau_levenshtein(other, command))
@@ -68,14 +270,60 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .position(|a| a == words.iter().min().unwrap())
                     .unwrap();
 
-                unrecoverable_error!(format!(
-                    "Subcommand `{}` doesn't exist! Did you mean `{}`?",
-                    other, SUBCOMMANDS[closest_word_index]
-                ));
+                exit_with_errors(
+                    vec![Diagnostic::error(format!(
+                        "Subcommand `{}` doesn't exist! Did you mean `{}`?",
+                        other, SUBCOMMANDS[closest_word_index]
+                    ))],
+                    &SourceMap::default(),
+                    format,
+                );
             } else {
-                unrecoverable_error!(format!("Subcommand `{}` doesn't exist!", other));
+                exit_with_errors(
+                    vec![Diagnostic::error(format!(
+                        "Subcommand `{}` doesn't exist!",
+                        other
+                    ))],
+                    &SourceMap::default(),
+                    format,
+                );
             }
         }
     }
-    Ok(())
+
+    Ok(0)
+}
+
+/// Prints the warnings reported while compiling the project
+fn emit_warnings(project: &FireworkProject, format: ErrorFormat) {
+    for warning in project.take_warnings() {
+        warning.emit(&project.sources(), format);
+    }
+}
+
+/// Prints errors and exits with a failure status
+fn exit_with_errors(diagnostics: Vec<Diagnostic>, sources: &SourceMap, format: ErrorFormat) -> ! {
+    for diagnostic in diagnostics {
+        diagnostic.emit(sources, format);
+    }
+
+    process::exit(1)
+}
+
+/// Options selecting the machine code is generated for
+fn target_args<'a, 'b>() -> [Arg<'a, 'b>; 3] {
+    [
+        Arg::with_name("target")
+            .long("target")
+            .takes_value(true)
+            .value_name("triple")
+            .help("Target triple to generate code for, e.g. aarch64-unknown-linux-gnu"),
+        Arg::with_name("cpu").long("cpu").takes_value(true).help(
+            "CPU to generate code for, defaults to the host's or generic when cross-compiling",
+        ),
+        Arg::with_name("features")
+            .long("features")
+            .takes_value(true)
+            .help("CPU features to enable or disable, e.g. +avx2,-sse4.1"),
+    ]
 }
@@ -0,0 +1,243 @@
+//! Module name resolution
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostics::Diagnostic;
+use crate::error::FireworkError;
+use crate::parser::{AstNode, SourceModule, Span};
+
+/// Returns the symbol a function defined in a module is compiled to
+///
+/// Functions of the ```main``` module keep their names, functions of other modules are
+/// prefixed with the module's name so that private functions of different modules never collide
+pub fn mangle(module: &str, name: &str) -> String {
+    if module == "main" {
+        name.to_string()
+    } else {
+        format!("{}::{}", module, name)
+    }
+}
+
+/// Returns the names of the functions defined in a module
+fn definitions(module: &SourceModule) -> Vec<String> {
+    module
+        .ast
+        .iter()
+        .filter_map(|node| match node {
+            AstNode::Fn { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Returns the names of the functions a module defines with the ```export``` annotation, whose
+/// symbols are never mangled so that they can be called from C
+fn unmangled_definitions(module: &SourceModule) -> HashSet<String> {
+    module
+        .ast
+        .iter()
+        .filter_map(|node| match node {
+            AstNode::Fn {
+                name,
+                exported: true,
+                ..
+            } => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>()
+}
+
+/// Returns the symbol a function defined in a module is compiled to
+fn definition_symbol(module: &SourceModule, name: &str) -> String {
+    if unmangled_definitions(module).contains(name) {
+        name.to_string()
+    } else {
+        mangle(&module.name, name)
+    }
+}
+
+/// Returns the span of a module's ```module``` declaration, if it has one
+fn declaration_span(module: &SourceModule) -> Option<Span> {
+    module.ast.iter().find_map(|node| match node {
+        AstNode::ModuleDeclaration { span, .. } => Some(*span),
+        _ => None,
+    })
+}
+
+/// Returns the names a module exports, modules without an export list export everything
+fn exports(module: &SourceModule) -> Vec<String> {
+    module
+        .ast
+        .iter()
+        .find_map(|node| match node {
+            AstNode::ModuleDeclaration {
+                exports: Some(exports),
+                ..
+            } => Some(exports.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| definitions(module))
+}
+
+/// Names visible from the module that is being compiled
+#[derive(Debug, Default)]
+pub struct ModuleScope {
+    /// Name of the module, ```None``` for code whose symbols aren't mangled like the core
+    module: Option<String>,
+    /// Maps every visible name to the symbols it could refer to
+    names: HashMap<String, Vec<String>>,
+    /// Qualified names of functions that imported modules don't export
+    private: HashSet<String>,
+    /// Functions of the module whose symbols aren't mangled
+    unmangled: HashSet<String>,
+}
+
+impl ModuleScope {
+    /// Collects the names a module defines and imports from the other modules
+    pub fn new(module: &SourceModule, modules: &[SourceModule]) -> Result<Self, FireworkError> {
+        let mut scope = Self {
+            module: Some(module.name.clone()),
+            unmangled: unmangled_definitions(module),
+            ..Self::default()
+        };
+
+        let local_definitions = definitions(module);
+
+        for export in exports(module) {
+            if !local_definitions.contains(&export) {
+                let mut diagnostic = Diagnostic::error(format!(
+                    "Module `{}` exports `{}` but doesn't define it",
+                    module.name, export
+                ));
+
+                if let Some(span) = declaration_span(module) {
+                    diagnostic = diagnostic.with_span(span);
+                }
+
+                return Err(diagnostic.into());
+            }
+        }
+
+        for node in &module.ast {
+            if let AstNode::ModuleImport {
+                module: imported,
+                alias,
+                names,
+                span,
+            } = node
+            {
+                let imported_name = match &**imported {
+                    AstNode::Module(name, _) => name,
+                    _ => unreachable!(),
+                };
+                let imported = modules
+                    .iter()
+                    .find(|module| &module.name == imported_name)
+                    .ok_or_else(|| {
+                        Diagnostic::error(format!("Module `{}` wasn't loaded", imported_name))
+                            .with_span(*span)
+                    })?;
+
+                scope
+                    .import(imported, alias.as_deref(), names.as_deref())
+                    .map_err(|diagnostic| (*diagnostic).with_span(*span))?;
+            }
+        }
+
+        for definition in local_definitions {
+            let symbol = scope.definition_symbol(&definition);
+            scope.names.insert(definition, vec![symbol]);
+        }
+
+        Ok(scope)
+    }
+
+    /// Adds the names of an ```import``` statement to the scope
+    ///
+    /// Every exported function can be referred to by its qualified name, using the alias as
+    /// the qualifier if there is one. Unqualified names are either the ones listed in the
+    /// import or, for imports without a list or an alias, every exported function.
+    fn import(
+        &mut self,
+        module: &SourceModule,
+        alias: Option<&str>,
+        names: Option<&[String]>,
+    ) -> Result<(), Box<Diagnostic>> {
+        let exports = exports(module);
+        let qualifier = alias.unwrap_or(&module.name);
+
+        for name in names.unwrap_or_default() {
+            if !exports.contains(name) {
+                if definitions(module).contains(name) {
+                    return Err(Box::new(Diagnostic::error(format!(
+                        "`{}` is private to module `{}`",
+                        name, module.name
+                    ))));
+                } else {
+                    return Err(Box::new(Diagnostic::error(format!(
+                        "Module `{}` doesn't define `{}`",
+                        module.name, name
+                    ))));
+                }
+            }
+        }
+
+        for definition in definitions(module) {
+            let qualified_name = format!("{}::{}", qualifier, definition);
+
+            if !exports.contains(&definition) {
+                self.private.insert(qualified_name);
+                continue;
+            }
+
+            let symbol = definition_symbol(module, &definition);
+            self.add_name(qualified_name, symbol.clone());
+
+            let unqualified = match names {
+                Some(names) => names.contains(&definition),
+                None => alias.is_none(),
+            };
+
+            if unqualified {
+                self.add_name(definition, symbol);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_name(&mut self, name: String, symbol: String) {
+        let symbols = self.names.entry(name).or_default();
+
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+
+    /// Returns the symbol a function defined in this module is compiled to
+    pub fn definition_symbol(&self, name: &str) -> String {
+        match &self.module {
+            Some(module) if !self.unmangled.contains(name) => mangle(module, name),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Returns the symbol a name refers to
+    ///
+    /// Names that aren't defined or imported are left as they are, since they may refer to
+    /// functions of the core
+    pub fn resolve(&self, name: &str) -> Result<String, Box<Diagnostic>> {
+        match self.names.get(name).map(|symbols| &symbols[..]) {
+            Some([symbol]) => Ok(symbol.clone()),
+            Some(symbols) => Err(Box::new(Diagnostic::error(format!(
+                "`{}` is ambiguous, it could refer to any of {}",
+                name,
+                symbols.join(", ")
+            )))),
+            None if self.private.contains(name) => Err(Box::new(Diagnostic::error(format!(
+                "`{}` is private to its module",
+                name
+            )))),
+            None => Ok(name.to_string()),
+        }
+    }
+}
@@ -4,82 +4,495 @@
 pub(crate) struct FireworkParser;
 
 use self::AstNode::*;
-use pest::error::Error;
-use pest::iterators::Pair;
+use crate::diagnostics::Diagnostic;
+use crate::error::FireworkError;
+use pest::error::InputLocation;
+use pest::iterators::{Pair, Pairs};
 use pest::Parser;
+use std::path::PathBuf;
 
 /// Represents a Firework program's Abstract Syntax Tree
 pub type AST = Vec<AstNode>;
 
+/// Represents a parsed Firework source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceModule {
+    /// Identifies the module's source file in spans
+    pub id: FileId,
+    /// Name used to import the module, e.g. ```foo::bar```
+    pub name: String,
+    /// Path of the module's source file
+    pub path: PathBuf,
+    /// Contents of the module's source file
+    pub source: String,
+    pub ast: AST,
+}
+
+/// A token of a Firework program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    /// Text matched by one of the grammar's innermost rules, e.g. a name or a literal
+    Rule(Rule),
+    /// A keyword of the grammar, e.g. ```let```
+    Keyword,
+    /// Punctuation of the grammar, e.g. ```(```
+    Punctuation,
+}
+
+/// Position of a token in its source file, both line and column start at 1
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Identifies a source file, modules are numbered in the order they're parsed
+pub type FileId = usize;
+
+/// Part of a source file a node was parsed from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub file: FileId,
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset after the last character
+    pub end: usize,
+    /// Line of the first character, starting at 1
+    pub line: usize,
+    /// Column of the first character, starting at 1
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns where the span starts
+    pub fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
 /// Represents an Abstract Syntax Tree's node
+///
+/// Every node but ```Eoi``` carries the span it was parsed from
 #[derive(Debug, Clone, PartialEq)]
 pub enum AstNode {
-    Str(String),
-    Int(i64),
-    Char(char),
-    Boolean(bool),
-    List(Vec<AstNode>),
-    InParens(Box<AstNode>),
-    Type(String),
-    FnArgs(Vec<(self::AstNode, self::AstNode)>),
-    ModuleImport(Box<self::AstNode>),
-    ModuleDeclaration(Box<self::AstNode>),
-    Module(String),
+    Unit(Span),
+    Str(String, Span),
+    Int(i64, Span),
+    Float(f64, Span),
+    Char(char, Span),
+    Boolean(bool, Span),
+    List(Vec<AstNode>, Span),
+    InParens(Box<AstNode>, Span),
+    Type(String, Span),
+    /// Pointer to a value of a type, e.g. ```*i8```
+    PointerType(Box<self::AstNode>, Span),
+    /// A type taking type arguments, e.g. ```Result i64 str```
+    GenericType {
+        name: String,
+        args: Vec<self::AstNode>,
+        span: Span,
+    },
+    FnArgs(Vec<(self::AstNode, self::AstNode)>, Span),
+    ModuleImport {
+        module: Box<self::AstNode>,
+        alias: Option<String>,
+        names: Option<Vec<String>>,
+        span: Span,
+    },
+    ModuleDeclaration {
+        module: Box<self::AstNode>,
+        exports: Option<Vec<String>>,
+        span: Span,
+    },
+    Module(String, Span),
     Identifier {
         name: String,
         args: Vec<self::AstNode>,
+        span: Span,
     },
     Enum {
         name: String,
         variants: Vec<self::AstNode>,
+        span: Span,
     },
     AnonFn {
         args: Box<self::AstNode>,
         return_type: Box<self::AstNode>,
         value: Box<self::AstNode>,
+        span: Span,
     },
     Fn {
         name: String,
         return_type: Box<self::AstNode>,
         args: Box<self::AstNode>,
         value: Box<self::AstNode>,
+        /// Whether the function is annotated with ```export```, which keeps its symbol unmangled
+        /// and makes it use the C calling convention
+        exported: bool,
+        span: Span,
+    },
+    /// Function implemented in another language, e.g. ```extern "C" let strlen(s: *i8): u64```
+    ExternFn {
+        abi: String,
+        name: String,
+        args: Box<self::AstNode>,
+        return_type: Box<self::AstNode>,
+        variadic: bool,
+        span: Span,
     },
     IfElse {
         condition: Box<self::AstNode>,
         stmt_true: Box<self::AstNode>,
         stmt_false: Box<self::AstNode>,
+        span: Span,
+    },
+    Match {
+        value: Box<self::AstNode>,
+        arms: Vec<(self::AstNode, self::AstNode)>,
+        span: Span,
+    },
+    /// Pattern of a match arm, e.g. ```Some(x)```, ```None``` or ```_```
+    Pattern {
+        constructor: String,
+        binding: Option<String>,
+        span: Span,
+    },
+    /// Postfix ```?```, returns early from the enclosing function on ```None``` or ```Err```
+    Try(Box<self::AstNode>, Span),
+    Do(Vec<self::AstNode>, Span),
+    LetBinding {
+        name: String,
+        value_type: Option<Box<self::AstNode>>,
+        value: Box<self::AstNode>,
+        span: Span,
     },
     Eoi,
 }
 
+impl AstNode {
+    /// Returns the span the node was parsed from, ```Eoi``` has an empty span
+    pub fn span(&self) -> Span {
+        match self {
+            Unit(span)
+            | Str(_, span)
+            | Int(_, span)
+            | Float(_, span)
+            | Char(_, span)
+            | Boolean(_, span)
+            | List(_, span)
+            | InParens(_, span)
+            | Type(_, span)
+            | PointerType(_, span)
+            | FnArgs(_, span)
+            | Module(_, span)
+            | Try(_, span)
+            | Do(_, span)
+            | GenericType { span, .. }
+            | ModuleImport { span, .. }
+            | ModuleDeclaration { span, .. }
+            | Identifier { span, .. }
+            | Enum { span, .. }
+            | AnonFn { span, .. }
+            | Fn { span, .. }
+            | ExternFn { span, .. }
+            | IfElse { span, .. }
+            | Match { span, .. }
+            | Pattern { span, .. }
+            | LetBinding { span, .. } => *span,
+            Eoi => Span::default(),
+        }
+    }
+}
+
 /// Parses a Firework program and transforms pest's output to a custom AST
-pub fn parse(input: &str) -> Result<AST, Error<Rule>> {
-    Ok(FireworkParser::parse(Rule::program, input)?
-        .into_iter()
-        .map(build_ast)
-        .filter(|node| !matches!(node, Eoi))
-        .collect::<AST>())
+pub fn parse(input: &str) -> Result<AST, FireworkError> {
+    parse_file(input, 0)
+}
+
+/// Parses a source file, the spans of its nodes point to ```file```
+///
+/// Every syntax error of the file is reported, see ```parse_recovering```
+pub fn parse_file(input: &str, file: FileId) -> Result<AST, FireworkError> {
+    match parse_recovering(input, file) {
+        (ast, diagnostics) if diagnostics.is_empty() => Ok(ast),
+        (_, diagnostics) => Err(FireworkError::Parse(diagnostics)),
+    }
+}
+
+/// Parses a source file, skipping the statements that have syntax errors
+///
+/// Returns the statements that parsed, along with a diagnostic for each syntax error. After an
+/// error, parsing resumes at the next unindented line starting a statement, indented lines
+/// belong to the statement that failed, e.g. the ```let``` bindings of its ```do``` blocks.
+pub fn parse_recovering(input: &str, file: FileId) -> (AST, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    // Statements with syntax errors are blanked out, so the spans of the others don't move
+    let mut masked = input.to_string();
+
+    loop {
+        let err = match FireworkParser::parse(Rule::program, &masked) {
+            Ok(pairs) => {
+                let ast = build_statements(pairs, file, &mut diagnostics)
+                    .into_iter()
+                    .filter(|node| !matches!(node, Eoi))
+                    .collect::<AST>();
+
+                return (ast, diagnostics);
+            }
+            Err(err) => err,
+        };
+
+        diagnostics.push(Diagnostic::from_syntax_error(&err, &masked, file));
+
+        let error_start = match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+
+        // The prefix always parses, it holds the statements before the error. A part of the
+        // statement with the error may parse too, e.g. `let a: i64 = f` of `let a: i64 = f {`,
+        // so the statements starting on the error's statement line are skipped with it.
+        let prefix = FireworkParser::parse(Rule::program_prefix, &masked)
+            .unwrap()
+            .next()
+            .unwrap();
+        let error_line = statement_line(&masked, error_start);
+        let statement_start = prefix
+            .clone()
+            .into_inner()
+            .map(|pair| pair.as_span().start())
+            .filter(|&start| matches!(error_line, Some(line) if start >= line))
+            .last()
+            .unwrap_or_else(|| prefix.as_span().end());
+        let statement_end = resync_point(&masked, error_start.max(statement_start));
+        let statement = &masked[statement_start..statement_end];
+
+        // There's nothing left to skip, so the statements before the error are all that parse
+        if statement.chars().all(char::is_whitespace) {
+            let statements = prefix
+                .into_inner()
+                .filter(|pair| pair.as_span().start() < statement_start);
+            let ast = build_statements(statements, file, &mut diagnostics);
+
+            return (ast, diagnostics);
+        }
+
+        let blanked = statement
+            .chars()
+            .map(|c| match c {
+                '\n' | '\r' => c.to_string(),
+                // Multi-byte characters are replaced by as many spaces as they have bytes
+                c => " ".repeat(c.len_utf8()),
+            })
+            .collect::<String>();
+
+        masked.replace_range(statement_start..statement_end, &blanked);
+    }
+}
+
+/// Builds the AST of parsed statements, statements using a feature the compiler doesn't
+/// support yet are reported and skipped
+fn build_statements<'a>(
+    statements: impl Iterator<Item = Pair<'a, Rule>>,
+    file: FileId,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> AST {
+    let mut ast = vec![];
+
+    for statement in statements {
+        let list = statement
+            .clone()
+            .into_inner()
+            .flatten()
+            .find(|pair| pair.as_rule() == Rule::list);
+
+        match list {
+            Some(list) => diagnostics.push(
+                Diagnostic::error("List literals are not implemented yet")
+                    .with_code("codegen-error")
+                    .with_span(span(&list, file))
+                    .with_label("list literal"),
+            ),
+            None => ast.push(build_ast(statement, file)),
+        }
+    }
+
+    ast
+}
+
+/// Returns the start of the first unindented line after ```offset``` that starts a statement,
+/// or the end of the source if there is none
+fn resync_point(source: &str, offset: usize) -> usize {
+    let mut line_start = offset;
+
+    while let Some(newline) = source[line_start..].find('\n') {
+        line_start += newline + 1;
+
+        if starts_statement(&source[line_start..]) {
+            return line_start;
+        }
+    }
+
+    source.len()
+}
+
+/// Returns the start of the last unindented line starting a statement at or before ```offset```
+fn statement_line(source: &str, offset: usize) -> Option<usize> {
+    let mut line_end = offset;
+
+    loop {
+        let line_start = source[..line_end]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+
+        if starts_statement(&source[line_start..]) {
+            return Some(line_start);
+        } else if line_start == 0 {
+            return None;
+        }
+
+        line_end = line_start - 1;
+    }
+}
+
+/// Checks whether a line starts with a statement's keyword, without being indented
+fn starts_statement(line: &str) -> bool {
+    let first_word = line
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default();
+
+    STATEMENT_KEYWORDS.contains(&first_word)
+}
+
+/// Keywords statements start with
+const STATEMENT_KEYWORDS: [&str; 6] = ["let", "export", "extern", "import", "module", "enum"];
+
+/// Runs pest on a source file, syntax errors are described by diagnostics pointing to ```file```
+fn parse_program(input: &str, file: FileId) -> Result<Pairs<'_, Rule>, FireworkError> {
+    FireworkParser::parse(Rule::program, input)
+        .map_err(|err| FireworkError::Parse(vec![Diagnostic::from_syntax_error(&err, input, file)]))
+}
+
+/// Splits a Firework program into the tokens it's parsed from
+pub fn tokenize(input: &str) -> Result<Vec<Token>, FireworkError> {
+    let mut tokens = vec![];
+    let mut end = 0;
+
+    let leaves = parse_program(input, 0)?
+        .flatten()
+        .filter(|pair| pair.clone().into_inner().next().is_none() && pair.as_rule() != Rule::EOI);
+
+    // Keywords and punctuation aren't rules, they're the text between the rules' tokens
+    for pair in leaves {
+        let span = pair.as_span();
+
+        if span.start() < end {
+            continue;
+        }
+
+        tokenize_literals(input, end, span.start(), &mut tokens);
+        tokens.push(Token {
+            kind: TokenKind::Rule(pair.as_rule()),
+            text: pair.as_str().to_string(),
+            location: location(&pair),
+        });
+
+        end = span.end();
+    }
+
+    tokenize_literals(input, end, input.len(), &mut tokens);
+
+    Ok(tokens)
+}
+
+/// Splits the keywords and punctuation between two tokens, skipping whitespace and comments
+fn tokenize_literals(input: &str, start: usize, end: usize, tokens: &mut Vec<Token>) {
+    let mut index = start;
+
+    while index < end {
+        let rest = &input[index..end];
+        let first = rest.chars().next().unwrap();
+
+        let (kind, len) = if first.is_whitespace() {
+            (None, first.len_utf8())
+        } else if rest.starts_with("/*") {
+            let len = rest
+                .find("*/")
+                .map_or(rest.len(), |comment_end| comment_end + 2);
+            (None, len)
+        } else if first.is_alphanumeric() || first == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Some(TokenKind::Keyword), len)
+        } else if rest.starts_with("->") {
+            (Some(TokenKind::Punctuation), 2)
+        } else {
+            (Some(TokenKind::Punctuation), first.len_utf8())
+        };
+
+        if let Some(kind) = kind {
+            let (line, column) = pest::Position::new(input, index).unwrap().line_col();
+
+            tokens.push(Token {
+                kind,
+                text: rest[..len].to_string(),
+                location: Location { line, column },
+            });
+        }
+
+        index += len;
+    }
 }
 
 /// Builds a custom AST from pest's output
-fn build_ast(pair: Pair<Rule>) -> AstNode {
+fn build_ast(pair: Pair<Rule>, file: FileId) -> AstNode {
+    let span = span(&pair, file);
+    let build = |pair| build_ast(pair, file);
+
     match pair.as_rule() {
         Rule::name => Identifier {
             name: pair.as_str().to_string(),
             args: vec![],
+            span,
         },
-        Rule::firework_type => Type(pair.as_str().to_string()),
-        Rule::type_signature => build_ast(pair.into_inner().next().unwrap()),
-        Rule::int => Int(pair.as_str().parse().unwrap()),
-        Rule::boolean => Boolean(pair.as_str().parse().unwrap()),
-        Rule::string => Str(pair.as_str().to_string().replace("\"", "")),
-        Rule::char => Char(pair.as_str().chars().nth(1).unwrap()),
-        Rule::literal => build_ast(pair.into_inner().next().unwrap()),
+        Rule::firework_type => {
+            let mut inner_pair = pair.into_inner();
+            let firework_type = build(inner_pair.next().unwrap());
+            let args = inner_pair.map(build).collect::<Vec<_>>();
+
+            match firework_type {
+                Type(name, _) if !args.is_empty() => GenericType { name, args, span },
+                firework_type => firework_type,
+            }
+        }
+        Rule::pointer_type => PointerType(Box::new(build(pair.into_inner().next().unwrap())), span),
+        Rule::type_name => Type(pair.as_str().to_string(), span),
+        Rule::type_signature => build(pair.into_inner().next().unwrap()),
+        Rule::int => Int(pair.as_str().parse().unwrap(), span),
+        Rule::float => Float(pair.as_str().parse().unwrap(), span),
+        Rule::boolean => Boolean(pair.as_str().parse().unwrap(), span),
+        Rule::string => Str(pair.as_str().to_string().replace("\"", ""), span),
+        Rule::char => Char(pair.as_str().chars().nth(1).unwrap(), span),
+        Rule::unit => Unit(span),
+        Rule::literal => build(pair.into_inner().next().unwrap()),
         Rule::identifier => {
             let mut inner_pair = pair.into_inner();
             Identifier {
                 name: inner_pair.next().unwrap().as_str().to_string(),
-                args: inner_pair.map(build_ast).collect::<Vec<AstNode>>(),
+                args: inner_pair.map(build).collect::<Vec<AstNode>>(),
+                span,
             }
         }
         Rule::enum_type => {
@@ -87,7 +500,8 @@ fn build_ast(pair: Pair<Rule>) -> AstNode {
 
             Enum {
                 name: inner_pair.next().unwrap().to_string(),
-                variants: inner_pair.map(build_ast).collect::<Vec<_>>(),
+                variants: inner_pair.map(build).collect::<Vec<_>>(),
+                span,
             }
         }
         Rule::fn_args => {
@@ -95,8 +509,8 @@ fn build_ast(pair: Pair<Rule>) -> AstNode {
             let mut types: Vec<AstNode> = vec![];
 
             pair.into_inner().for_each(|x| match x.as_rule() {
-                Rule::name => args.push(build_ast(x)),
-                Rule::type_signature => types.push(build_ast(x)),
+                Rule::name => args.push(build(x)),
+                Rule::type_signature => types.push(build(x)),
                 _ => unreachable!(),
             });
 
@@ -105,72 +519,227 @@ fn build_ast(pair: Pair<Rule>) -> AstNode {
                     .cloned()
                     .zip(types.iter().cloned())
                     .collect::<Vec<(_, _)>>(),
+                span,
             )
         }
         Rule::declaration => {
-            let mut inner_pair = pair.into_inner();
+            let mut inner_pair = pair.into_inner().peekable();
+            let exported = inner_pair
+                .next_if(|x| x.as_rule() == Rule::export_annotation)
+                .is_some();
             let name = inner_pair.next().unwrap();
             let args_or_type = inner_pair.next().unwrap();
 
             if let Rule::fn_args = args_or_type.as_rule() {
                 Fn {
                     name: name.as_str().to_string(),
-                    args: Box::new(build_ast(args_or_type)),
-                    return_type: Box::new(build_ast(inner_pair.next().unwrap())),
-                    value: Box::new(build_ast(inner_pair.next().unwrap())),
+                    args: Box::new(build(args_or_type)),
+                    return_type: Box::new(build(inner_pair.next().unwrap())),
+                    value: Box::new(build(inner_pair.next().unwrap())),
+                    exported,
+                    span,
                 }
             } else {
+                // A function without arguments takes them from the empty text after its name
+                let name_end = name.as_span().end_pos();
+                let (line, column) = name_end.line_col();
+                let args_span = Span {
+                    file,
+                    start: name_end.pos(),
+                    end: name_end.pos(),
+                    line,
+                    column,
+                };
+
                 Fn {
                     name: name.as_str().to_string(),
-                    args: Box::new(AstNode::FnArgs(Vec::new())),
-                    return_type: Box::new(build_ast(args_or_type)),
-                    value: Box::new(build_ast(inner_pair.next().unwrap())),
+                    args: Box::new(AstNode::FnArgs(Vec::new(), args_span)),
+                    return_type: Box::new(build(args_or_type)),
+                    value: Box::new(build(inner_pair.next().unwrap())),
+                    exported,
+                    span,
                 }
             }
         }
+        Rule::extern_declaration => {
+            let mut abi = String::new();
+            let mut name = String::new();
+            let mut args = FnArgs(Vec::new(), span);
+            let mut return_type = Unit(span);
+            let mut variadic = false;
+
+            pair.into_inner().for_each(|x| match x.as_rule() {
+                Rule::string => abi = x.as_str().replace("\"", ""),
+                Rule::fn_name => name = x.as_str().to_string(),
+                Rule::fn_args => args = build(x),
+                Rule::variadic => variadic = true,
+                Rule::type_signature => return_type = build(x),
+                _ => unreachable!(),
+            });
+
+            ExternFn {
+                abi,
+                name,
+                args: Box::new(args),
+                return_type: Box::new(return_type),
+                variadic,
+                span,
+            }
+        }
         Rule::anon_fn => {
             let mut inner_pair = pair.into_inner();
             let args_or_type = inner_pair.next().unwrap();
 
             if let Rule::fn_args = args_or_type.as_rule() {
                 AnonFn {
-                    args: Box::new(build_ast(args_or_type)),
-                    return_type: Box::new(build_ast(inner_pair.next().unwrap())),
-                    value: Box::new(build_ast(inner_pair.next().unwrap())),
+                    args: Box::new(build(args_or_type)),
+                    return_type: Box::new(build(inner_pair.next().unwrap())),
+                    value: Box::new(build(inner_pair.next().unwrap())),
+                    span,
                 }
             } else {
                 AnonFn {
-                    args: Box::new(FnArgs(Vec::new())),
-                    return_type: Box::new(build_ast(args_or_type)),
-                    value: Box::new(build_ast(inner_pair.next().unwrap())),
+                    args: Box::new(FnArgs(Vec::new(), span)),
+                    return_type: Box::new(build(args_or_type)),
+                    value: Box::new(build(inner_pair.next().unwrap())),
+                    span,
                 }
             }
         }
         Rule::module_name => Identifier {
             name: pair.as_str().to_string(),
             args: vec![],
+            span,
         },
-        Rule::module_import => ModuleImport(Box::new(Module(
-            pair.into_inner().next().unwrap().as_str().to_string(),
-        ))),
-        Rule::module_declaration => ModuleDeclaration(Box::new(Module(
-            pair.into_inner().next().unwrap().as_str().to_string(),
-        ))),
+        Rule::module_import => {
+            let mut inner_pair = pair.into_inner();
+            let module = build_module(inner_pair.next().unwrap(), file);
+            let mut alias = None;
+            let mut names = None;
+
+            inner_pair.for_each(|x| match x.as_rule() {
+                Rule::import_alias => {
+                    alias = Some(x.into_inner().next().unwrap().as_str().to_string())
+                }
+                Rule::name_list => names = Some(build_name_list(x)),
+                _ => unreachable!(),
+            });
+
+            ModuleImport {
+                module: Box::new(module),
+                alias,
+                names,
+                span,
+            }
+        }
+        Rule::module_declaration => {
+            let mut inner_pair = pair.into_inner();
+
+            ModuleDeclaration {
+                module: Box::new(build_module(inner_pair.next().unwrap(), file)),
+                exports: inner_pair.next().map(build_name_list),
+                span,
+            }
+        }
 
         Rule::if_statement => {
             let mut inner_pair = pair.into_inner();
             IfElse {
-                condition: Box::new(build_ast(inner_pair.next().unwrap())),
-                stmt_true: Box::new(build_ast(inner_pair.next().unwrap())),
-                stmt_false: Box::new(build_ast(inner_pair.next().unwrap())),
+                condition: Box::new(build(inner_pair.next().unwrap())),
+                stmt_true: Box::new(build(inner_pair.next().unwrap())),
+                stmt_false: Box::new(build(inner_pair.next().unwrap())),
+                span,
+            }
+        }
+        Rule::match_expr => {
+            let mut inner_pair = pair.into_inner();
+
+            Match {
+                value: Box::new(build(inner_pair.next().unwrap())),
+                arms: inner_pair
+                    .map(|arm| {
+                        let mut inner_pair = arm.into_inner();
+                        let pattern = build(inner_pair.next().unwrap());
+
+                        (pattern, build(inner_pair.next().unwrap()))
+                    })
+                    .collect::<Vec<_>>(),
+                span,
+            }
+        }
+        Rule::pattern => {
+            let mut inner_pair = pair.into_inner();
+
+            Pattern {
+                constructor: inner_pair.next().unwrap().as_str().to_string(),
+                binding: inner_pair
+                    .next()
+                    .map(|binding| binding.as_str().to_string()),
+                span,
             }
         }
-        Rule::repl => build_ast(pair.into_inner().next().unwrap()),
-        Rule::precedence => InParens(Box::new(build_ast(pair.into_inner().next().unwrap()))),
-        Rule::list => {
-            todo!()
+        Rule::try_expr => Try(Box::new(build(pair.into_inner().next().unwrap())), span),
+        Rule::do_block => Do(pair.into_inner().map(build).collect::<Vec<_>>(), span),
+        Rule::let_binding => {
+            let mut inner_pair = pair.into_inner();
+            let name = inner_pair.next().unwrap().as_str().to_string();
+            let type_or_value = inner_pair.next().unwrap();
+
+            if let Rule::type_signature = type_or_value.as_rule() {
+                LetBinding {
+                    name,
+                    value_type: Some(Box::new(build(type_or_value))),
+                    value: Box::new(build(inner_pair.next().unwrap())),
+                    span,
+                }
+            } else {
+                LetBinding {
+                    name,
+                    value_type: None,
+                    value: Box::new(build(type_or_value)),
+                    span,
+                }
+            }
         }
+        Rule::repl => build(pair.into_inner().next().unwrap()),
+        Rule::precedence => InParens(Box::new(build(pair.into_inner().next().unwrap())), span),
+        // Statements with list literals are reported by `build_statements` and never built
+        Rule::list => unreachable!(),
         Rule::EOI => Eoi,
         _ => unreachable!(),
     }
 }
+
+/// Returns where a token starts in the source
+fn location(pair: &Pair<Rule>) -> Location {
+    let (line, column) = pair.as_span().start_pos().line_col();
+
+    Location { line, column }
+}
+
+/// Returns the part of the source a pair was parsed from
+fn span(pair: &Pair<Rule>, file: FileId) -> Span {
+    let pest_span = pair.as_span();
+    let (line, column) = pest_span.start_pos().line_col();
+
+    Span {
+        file,
+        start: pest_span.start(),
+        // Rules followed by optional parts can end with the whitespace before them
+        end: pest_span.start() + pest_span.as_str().trim_end().len(),
+        line,
+        column,
+    }
+}
+
+/// Builds the name of an imported or declared module
+fn build_module(pair: Pair<Rule>, file: FileId) -> AstNode {
+    Module(pair.as_str().to_string(), span(&pair, file))
+}
+
+/// Collects the names of an import or export list
+fn build_name_list(pair: Pair<Rule>) -> Vec<String> {
+    pair.into_inner()
+        .map(|name| name.as_str().to_string())
+        .collect::<Vec<_>>()
+}
@@ -0,0 +1,340 @@
+; Firework's runtime for WebAssembly, it implements the parts of libc used by compiled programs
+; on top of WASI so that modules only import from wasi_snapshot_preview1
+;
+; printf and dprintf support the %d, %i, %u, %s, %c and %% conversions with the l and ll length
+; modifiers, other conversions are written as is
+;
+; The libc functions are external so that they're linked in even when unused, the compiler makes
+; them internal once they're linked
+;
+; __multi3 is the compiler-rt routine LLVM calls to multiply 128-bit integers, which checked 64-bit
+; multiplication needs, so that modules link without compiler-rt
+
+%iovec = type { i8*, i32 }
+
+@newline = private unnamed_addr constant [1 x i8] c"\0A"
+
+declare i32 @__wasi_fd_write(i32, %iovec*, i32, i32*) #0
+declare void @__wasi_proc_exit(i32) #1
+declare void @llvm.va_start(i8*)
+declare void @llvm.va_end(i8*)
+
+@llvm.used = appending global [1 x i8*] [i8* bitcast (i128 (i128, i128)* @__multi3 to i8*)], section "llvm.metadata"
+
+; Writes len bytes to a file descriptor, retrying until everything was written or writing fails
+define internal void @firework_write(i32 %fd, i8* %data, i32 %len) {
+entry:
+  %iov = alloca %iovec
+  %written = alloca i32
+  %iov_data = getelementptr %iovec, %iovec* %iov, i32 0, i32 0
+  %iov_len = getelementptr %iovec, %iovec* %iov, i32 0, i32 1
+  br label %loop
+
+loop:
+  %ptr = phi i8* [ %data, %entry ], [ %next_ptr, %partial ]
+  %remaining = phi i32 [ %len, %entry ], [ %next_remaining, %partial ]
+  %finished = icmp sle i32 %remaining, 0
+  br i1 %finished, label %done, label %write
+
+write:
+  store i8* %ptr, i8** %iov_data
+  store i32 %remaining, i32* %iov_len
+  %errno = call i32 @__wasi_fd_write(i32 %fd, %iovec* %iov, i32 1, i32* %written)
+  %count = load i32, i32* %written
+  %failed = icmp ne i32 %errno, 0
+  %stuck = icmp eq i32 %count, 0
+  %stop = or i1 %failed, %stuck
+  br i1 %stop, label %done, label %partial
+
+partial:
+  %next_ptr = getelementptr i8, i8* %ptr, i32 %count
+  %next_remaining = sub i32 %remaining, %count
+  br label %loop
+
+done:
+  ret void
+}
+
+define internal i32 @firework_strlen(i8* %string) {
+entry:
+  br label %loop
+
+loop:
+  %index = phi i32 [ 0, %entry ], [ %next, %loop ]
+  %ptr = getelementptr i8, i8* %string, i32 %index
+  %char = load i8, i8* %ptr
+  %next = add i32 %index, 1
+  %is_end = icmp eq i8 %char, 0
+  br i1 %is_end, label %done, label %loop
+
+done:
+  ret i32 %index
+}
+
+; Writes an integer in decimal, it's treated as unsigned unless is_signed is true
+define internal void @firework_write_int(i32 %fd, i64 %value, i1 %is_signed) {
+entry:
+  %buffer = alloca [21 x i8]
+  %is_below_zero = icmp slt i64 %value, 0
+  %negative = and i1 %is_signed, %is_below_zero
+  %negated = sub i64 0, %value
+  %magnitude = select i1 %negative, i64 %negated, i64 %value
+  %end = getelementptr [21 x i8], [21 x i8]* %buffer, i32 0, i32 21
+  br label %loop
+
+loop:
+  %rest = phi i64 [ %magnitude, %entry ], [ %quotient, %loop ]
+  %position = phi i8* [ %end, %entry ], [ %digit_ptr, %loop ]
+  %quotient = udiv i64 %rest, 10
+  %remainder = urem i64 %rest, 10
+  %digit = trunc i64 %remainder to i8
+  %char = add i8 %digit, 48
+  %digit_ptr = getelementptr i8, i8* %position, i32 -1
+  store i8 %char, i8* %digit_ptr
+  %has_digits = icmp ne i64 %quotient, 0
+  br i1 %has_digits, label %loop, label %sign
+
+sign:
+  %minus_ptr = getelementptr i8, i8* %digit_ptr, i32 -1
+  store i8 45, i8* %minus_ptr
+  %start = select i1 %negative, i8* %minus_ptr, i8* %digit_ptr
+  %start_address = ptrtoint i8* %start to i32
+  %end_address = ptrtoint i8* %end to i32
+  %len = sub i32 %end_address, %start_address
+  call void @firework_write(i32 %fd, i8* %start, i32 %len)
+  ret void
+}
+
+; Reads the next variadic argument, arguments are stored at their natural alignment
+define internal i32 @firework_va_i32(i8** %args) {
+entry:
+  %cursor = load i8*, i8** %args
+  %address = ptrtoint i8* %cursor to i32
+  %padded = add i32 %address, 3
+  %aligned = and i32 %padded, -4
+  %ptr = inttoptr i32 %aligned to i32*
+  %value = load i32, i32* %ptr
+  %next = add i32 %aligned, 4
+  %next_cursor = inttoptr i32 %next to i8*
+  store i8* %next_cursor, i8** %args
+  ret i32 %value
+}
+
+define internal i64 @firework_va_i64(i8** %args) {
+entry:
+  %cursor = load i8*, i8** %args
+  %address = ptrtoint i8* %cursor to i32
+  %padded = add i32 %address, 7
+  %aligned = and i32 %padded, -8
+  %ptr = inttoptr i32 %aligned to i64*
+  %value = load i64, i64* %ptr
+  %next = add i32 %aligned, 8
+  %next_cursor = inttoptr i32 %next to i8*
+  store i8* %next_cursor, i8** %args
+  ret i64 %value
+}
+
+define internal void @firework_vdprintf(i32 %fd, i8* %format, i8** %args) {
+entry:
+  %char_buffer = alloca i8
+  br label %loop
+
+loop:
+  %run = phi i8* [ %format, %entry ], [ %run, %literal ], [ %resume, %converted ]
+  %ptr = phi i8* [ %format, %entry ], [ %next_ptr, %literal ], [ %resume, %converted ]
+  %char = load i8, i8* %ptr
+  %next_ptr = getelementptr i8, i8* %ptr, i32 1
+  %is_end = icmp eq i8 %char, 0
+  br i1 %is_end, label %finish, label %check
+
+check:
+  %is_percent = icmp eq i8 %char, 37
+  br i1 %is_percent, label %flush, label %literal
+
+literal:
+  br label %loop
+
+flush:
+  %run_start = ptrtoint i8* %run to i32
+  %run_end = ptrtoint i8* %ptr to i32
+  %run_len = sub i32 %run_end, %run_start
+  call void @firework_write(i32 %fd, i8* %run, i32 %run_len)
+  br label %length
+
+length:
+  %spec_ptr = phi i8* [ %next_ptr, %flush ], [ %resume, %long ]
+  %longs = phi i32 [ 0, %flush ], [ %more_longs, %long ]
+  %spec = load i8, i8* %spec_ptr
+  %resume = getelementptr i8, i8* %spec_ptr, i32 1
+  %is_long = icmp eq i8 %spec, 108
+  br i1 %is_long, label %long, label %dispatch
+
+long:
+  %more_longs = add i32 %longs, 1
+  br label %length
+
+dispatch:
+  %is_wide = icmp ne i32 %longs, 0
+  switch i8 %spec, label %unknown [
+    i8 100, label %signed
+    i8 105, label %signed
+    i8 117, label %unsigned
+    i8 115, label %string
+    i8 99, label %char_conversion
+    i8 37, label %percent
+    i8 0, label %done
+  ]
+
+signed:
+  br i1 %is_wide, label %signed_wide, label %signed_narrow
+
+signed_wide:
+  %signed_wide_value = call i64 @firework_va_i64(i8** %args)
+  call void @firework_write_int(i32 %fd, i64 %signed_wide_value, i1 true)
+  br label %converted
+
+signed_narrow:
+  %signed_narrow_value = call i32 @firework_va_i32(i8** %args)
+  %signed_extended = sext i32 %signed_narrow_value to i64
+  call void @firework_write_int(i32 %fd, i64 %signed_extended, i1 true)
+  br label %converted
+
+unsigned:
+  br i1 %is_wide, label %unsigned_wide, label %unsigned_narrow
+
+unsigned_wide:
+  %unsigned_wide_value = call i64 @firework_va_i64(i8** %args)
+  call void @firework_write_int(i32 %fd, i64 %unsigned_wide_value, i1 false)
+  br label %converted
+
+unsigned_narrow:
+  %unsigned_narrow_value = call i32 @firework_va_i32(i8** %args)
+  %unsigned_extended = zext i32 %unsigned_narrow_value to i64
+  call void @firework_write_int(i32 %fd, i64 %unsigned_extended, i1 false)
+  br label %converted
+
+string:
+  %string_address = call i32 @firework_va_i32(i8** %args)
+  %string_value = inttoptr i32 %string_address to i8*
+  %string_len = call i32 @firework_strlen(i8* %string_value)
+  call void @firework_write(i32 %fd, i8* %string_value, i32 %string_len)
+  br label %converted
+
+char_conversion:
+  %char_value = call i32 @firework_va_i32(i8** %args)
+  %char_byte = trunc i32 %char_value to i8
+  store i8 %char_byte, i8* %char_buffer
+  call void @firework_write(i32 %fd, i8* %char_buffer, i32 1)
+  br label %converted
+
+percent:
+  call void @firework_write(i32 %fd, i8* %spec_ptr, i32 1)
+  br label %converted
+
+unknown:
+  %unknown_start = ptrtoint i8* %ptr to i32
+  %unknown_end = ptrtoint i8* %resume to i32
+  %unknown_len = sub i32 %unknown_end, %unknown_start
+  call void @firework_write(i32 %fd, i8* %ptr, i32 %unknown_len)
+  br label %converted
+
+converted:
+  br label %loop
+
+finish:
+  %finish_start = ptrtoint i8* %run to i32
+  %finish_end = ptrtoint i8* %ptr to i32
+  %finish_len = sub i32 %finish_end, %finish_start
+  call void @firework_write(i32 %fd, i8* %run, i32 %finish_len)
+  br label %done
+
+done:
+  ret void
+}
+
+define i64 @puts(i8* %string) {
+entry:
+  %len = call i32 @firework_strlen(i8* %string)
+  call void @firework_write(i32 1, i8* %string, i32 %len)
+  %newline = getelementptr [1 x i8], [1 x i8]* @newline, i32 0, i32 0
+  call void @firework_write(i32 1, i8* %newline, i32 1)
+  ret i64 0
+}
+
+define i64 @printf(i8* %format, ...) {
+entry:
+  %args = alloca i8*
+  %list = bitcast i8** %args to i8*
+  call void @llvm.va_start(i8* %list)
+  call void @firework_vdprintf(i32 1, i8* %format, i8** %args)
+  call void @llvm.va_end(i8* %list)
+  ret i64 0
+}
+
+define i32 @dprintf(i32 %fd, i8* %format, ...) {
+entry:
+  %args = alloca i8*
+  %list = bitcast i8** %args to i8*
+  call void @llvm.va_start(i8* %list)
+  call void @firework_vdprintf(i32 %fd, i8* %format, i8** %args)
+  call void @llvm.va_end(i8* %list)
+  ret i32 0
+}
+
+; Multiplies 128-bit integers with 32-bit partial products, a 128-bit mul would call itself
+define i128 @__multi3(i128 %a, i128 %b) {
+entry:
+  %a_low = trunc i128 %a to i64
+  %a_shifted = lshr i128 %a, 64
+  %a_high = trunc i128 %a_shifted to i64
+  %b_low = trunc i128 %b to i64
+  %b_shifted = lshr i128 %b, 64
+  %b_high = trunc i128 %b_shifted to i64
+
+  %a0 = and i64 %a_low, 4294967295
+  %a1 = lshr i64 %a_low, 32
+  %b0 = and i64 %b_low, 4294967295
+  %b1 = lshr i64 %b_low, 32
+
+  %p00 = mul i64 %a0, %b0
+  %p01 = mul i64 %a0, %b1
+  %p10 = mul i64 %a1, %b0
+  %p11 = mul i64 %a1, %b1
+
+  %p00_high = lshr i64 %p00, 32
+  %p01_low = and i64 %p01, 4294967295
+  %p10_low = and i64 %p10, 4294967295
+  %middle_partial = add i64 %p00_high, %p01_low
+  %middle = add i64 %middle_partial, %p10_low
+
+  %p00_low = and i64 %p00, 4294967295
+  %middle_shifted = shl i64 %middle, 32
+  %low = or i64 %p00_low, %middle_shifted
+
+  %p01_high = lshr i64 %p01, 32
+  %p10_high = lshr i64 %p10, 32
+  %middle_high = lshr i64 %middle, 32
+  %high_0 = add i64 %p11, %p01_high
+  %high_1 = add i64 %high_0, %p10_high
+  %high_2 = add i64 %high_1, %middle_high
+  %cross_0 = mul i64 %a_low, %b_high
+  %cross_1 = mul i64 %a_high, %b_low
+  %high_3 = add i64 %high_2, %cross_0
+  %high = add i64 %high_3, %cross_1
+
+  %low_wide = zext i64 %low to i128
+  %high_wide = zext i64 %high to i128
+  %high_shifted = shl i128 %high_wide, 64
+  %result = or i128 %high_shifted, %low_wide
+  ret i128 %result
+}
+
+define void @exit(i32 %code) #2 {
+entry:
+  call void @__wasi_proc_exit(i32 %code)
+  unreachable
+}
+
+attributes #0 = { "wasm-import-module"="wasi_snapshot_preview1" "wasm-import-name"="fd_write" }
+attributes #1 = { noreturn "wasm-import-module"="wasi_snapshot_preview1" "wasm-import-name"="proc_exit" }
+attributes #2 = { noreturn }
@@ -0,0 +1,77 @@
+//! Runs a project built for WASI in wasmtime, skipped when wasmtime or ```wasm-ld``` are missing
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns whether a program of the system's toolchain can be run
+fn is_installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Temporary project directory, removed when the test ends even if it fails
+struct TempProject(PathBuf);
+
+impl Drop for TempProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn runs_wasi_builds_in_wasmtime() {
+    let linker = std::env::var("WASM_LD").unwrap_or_else(|_| "wasm-ld".to_string());
+    if !is_installed("wasmtime") || !is_installed(&linker) {
+        eprintln!(
+            "Skipping, wasmtime and {} are needed to run WASI builds",
+            linker
+        );
+        return;
+    }
+
+    let project =
+        TempProject(std::env::temp_dir().join(format!("firework_wasi_{}", std::process::id())));
+    fs::create_dir_all(project.0.join("src")).unwrap();
+    fs::write(
+        project.0.join("src/main.firework"),
+        "let main: Unit = puts(\"Hello World!\")\n",
+    )
+    .unwrap();
+
+    // An empty core in a home directory of its own keeps the build from downloading the core
+    let home = project.0.join("home");
+    fs::create_dir_all(home.join(".firework_lang")).unwrap();
+    fs::write(home.join(".firework_lang/core.firework"), "").unwrap();
+
+    let build = Command::new(env!("CARGO_BIN_EXE_firework_lang"))
+        .arg("build")
+        .arg("--target")
+        .arg("wasm32-wasi")
+        .current_dir(&project.0)
+        .env("HOME", &home)
+        .env("USERPROFILE", &home)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let module = Path::new("target").join(format!(
+        "{}.wasm",
+        project.0.file_name().unwrap().to_string_lossy()
+    ));
+    let run = Command::new("wasmtime")
+        .arg(&module)
+        .current_dir(&project.0)
+        .output()
+        .unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "Hello World!\n");
+    assert_eq!(run.status.code(), Some(0));
+}